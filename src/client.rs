@@ -0,0 +1,209 @@
+use crate::errors;
+use crate::token_store::TokenStore;
+use crate::{HomeAssistantAPI, Inner, Token};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+
+const DEFAULT_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// Configures the HTTP client shared by every request a `HomeAssistantAPI` makes.
+///
+/// TLS settings apply to websocket connections as well, proxies only apply to HTTP
+/// requests. Like for HTTP requests, `connect_timeout` bounds opening the TCP connection
+/// and `timeout` the whole websocket handshake, from connecting until HA accepts the
+/// token. Commands on an open connection never time out.
+#[derive(Debug)]
+pub struct ClientBuilder {
+    instance_url: String,
+    client_id: String,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    proxies: Vec<reqwest::Proxy>,
+    no_proxy: bool,
+    tls: TlsConfig,
+    websocket: WebSocketConfig,
+    user_agent: String,
+    token_store: Option<Box<dyn TokenStore>>,
+}
+
+/// TLS settings kept around to build connectors for websocket connections.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TlsConfig {
+    root_certificates: Vec<Vec<u8>>,
+    accept_invalid_certs: bool,
+}
+
+/// Keepalive and reconnection settings of websocket connections.
+#[derive(Debug, Clone)]
+pub(crate) struct WebSocketConfig {
+    pub(crate) ping_interval: Duration,
+    pub(crate) min_reconnect_delay: Duration,
+    pub(crate) max_reconnect_delay: Duration,
+    /// Bounds opening the TCP connection.
+    pub(crate) connect_timeout: Option<Duration>,
+    /// Bounds connecting, the TLS and websocket handshakes and authenticating.
+    pub(crate) handshake_timeout: Option<Duration>,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(30),
+            min_reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(60),
+            connect_timeout: None,
+            handshake_timeout: None,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub(crate) fn connector(&self) -> Result<native_tls::TlsConnector, errors::Error> {
+        let mut builder = native_tls::TlsConnector::builder();
+        for pem in &self.root_certificates {
+            builder.add_root_certificate(native_tls::Certificate::from_pem(pem)?);
+        }
+        builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+        Ok(builder.build()?)
+    }
+}
+
+impl ClientBuilder {
+    pub fn new(instance_url: String, client_id: String) -> Self {
+        Self {
+            instance_url,
+            client_id,
+            connect_timeout: None,
+            timeout: None,
+            proxies: Vec::new(),
+            no_proxy: false,
+            tls: TlsConfig::default(),
+            websocket: WebSocketConfig::default(),
+            user_agent: String::from(DEFAULT_USER_AGENT),
+            token_store: None,
+        }
+    }
+
+    /// Timeout for establishing a connection to Home Assistant.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Timeout for a whole HTTP request, from connecting until the body is read.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: reqwest::Proxy) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Ignores proxies configured through the environment.
+    pub fn no_proxy(mut self) -> Self {
+        self.no_proxy = true;
+        self
+    }
+
+    /// Trusts an additional PEM encoded root certificate, e.g. of a self signed install.
+    pub fn add_root_certificate_pem(mut self, pem: &[u8]) -> Self {
+        self.tls.root_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Accepts any certificate, including expired ones and ones for other hosts.
+    ///
+    /// This makes the connection vulnerable to man in the middle attacks, prefer
+    /// `add_root_certificate_pem` for self signed certificates.
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.tls.accept_invalid_certs = accept;
+        self
+    }
+
+    /// How often websocket connections are pinged, a connection is considered lost once a
+    /// ping goes unanswered until the next one is due. Must not be zero.
+    pub fn websocket_ping_interval(mut self, interval: Duration) -> Self {
+        self.websocket.ping_interval = interval;
+        self
+    }
+
+    /// Delay before reconnecting a lost websocket connection, doubled after every failed
+    /// attempt up to `max`. `min` must be above zero and at most `max`.
+    pub fn websocket_reconnect_delay(mut self, min: Duration, max: Duration) -> Self {
+        self.websocket.min_reconnect_delay = min;
+        self.websocket.max_reconnect_delay = max;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Restores the token saved in `store` and persists every later token change to it.
+    pub fn token_store(mut self, store: Box<dyn TokenStore>) -> Self {
+        self.token_store = Some(store);
+        self
+    }
+
+    pub fn build(mut self) -> Result<HomeAssistantAPI, errors::Error> {
+        if self.websocket.ping_interval == Duration::from_secs(0) {
+            return Err(errors::Error::Config(String::from(
+                "The websocket ping interval must not be zero",
+            )));
+        }
+        if self.websocket.min_reconnect_delay == Duration::from_secs(0)
+            || self.websocket.min_reconnect_delay > self.websocket.max_reconnect_delay
+        {
+            return Err(errors::Error::Config(String::from(
+                "The websocket reconnect delay must be above zero and at most its maximum",
+            )));
+        }
+
+        let mut http = reqwest::Client::builder()
+            .user_agent(self.user_agent)
+            .danger_accept_invalid_certs(self.tls.accept_invalid_certs);
+        if let Some(timeout) = self.connect_timeout {
+            http = http.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            http = http.timeout(timeout);
+        }
+        if self.no_proxy {
+            http = http.no_proxy();
+        }
+        for proxy in self.proxies {
+            http = http.proxy(proxy);
+        }
+        for pem in &self.tls.root_certificates {
+            http = http.add_root_certificate(reqwest::Certificate::from_pem(pem)?);
+        }
+        // surfaces invalid certificates now instead of on the first websocket connection
+        self.tls.connector()?;
+
+        self.websocket.connect_timeout = self.connect_timeout;
+        self.websocket.handshake_timeout = self.timeout;
+
+        let token = match &self.token_store {
+            Some(store) => store.load()?.unwrap_or(Token::None),
+            None => Token::None,
+        };
+
+        Ok(HomeAssistantAPI {
+            inner: Arc::new(Inner {
+                instance_url: self.instance_url,
+                client_id: self.client_id,
+                http: http.build()?,
+                tls: self.tls,
+                websocket: self.websocket,
+                token: RwLock::new(token),
+                token_store: RwLock::new(self.token_store.map(Arc::from)),
+                persist_lock: Mutex::new(()),
+                refresh_lock: Mutex::new(()),
+            }),
+        })
+    }
+}
@@ -0,0 +1,644 @@
+//! Typed views of the entities of common domains, built from a `StateObject` with
+//! `TryFrom` and calling their services through `Rest::service_call`.
+//!
+//! ```no_run
+//! # use homeassistant::domains::{Light, LightTurnOn};
+//! # use std::convert::TryFrom;
+//! # async fn example(rest: homeassistant::rest::Rest) -> Result<(), homeassistant::errors::Error> {
+//! let light = Light::try_from(rest.state_of(&"light.kitchen".parse()?).await?)?;
+//! if !light.is_on() {
+//!     light.turn_on(&rest, LightTurnOn { brightness_pct: Some(60), ..Default::default() }).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::errors;
+use crate::rest::Rest;
+use crate::types::StateObject;
+use bitflags::bitflags;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+macro_rules! domain_view {
+    ($($(#[$doc:meta])* $view:ident => $domain:literal),* $(,)?) => {
+        $(
+            $(#[$doc])*
+            #[derive(Debug, Clone)]
+            pub struct $view(StateObject);
+
+            impl $view {
+                pub const DOMAIN: &'static str = $domain;
+
+                pub fn into_inner(self) -> StateObject {
+                    self.0
+                }
+            }
+
+            impl TryFrom<StateObject> for $view {
+                type Error = errors::Error;
+
+                fn try_from(state: StateObject) -> Result<Self, Self::Error> {
+                    if state.domain() == Self::DOMAIN {
+                        Ok(Self(state))
+                    } else {
+                        Err(errors::Error::WrongDomain(
+                            Self::DOMAIN.to_string(),
+                            state.entity_id,
+                        ))
+                    }
+                }
+            }
+
+            impl std::ops::Deref for $view {
+                type Target = StateObject;
+
+                fn deref(&self) -> &StateObject {
+                    &self.0
+                }
+            }
+        )*
+    };
+}
+
+domain_view!(
+    Light => "light",
+    Climate => "climate",
+    Cover => "cover",
+    MediaPlayer => "media_player",
+    Sensor => "sensor",
+    BinarySensor => "binary_sensor",
+    Lock => "lock",
+    Fan => "fan",
+    Switch => "switch",
+);
+
+bitflags! {
+    pub struct LightFeatures: u32 {
+        const EFFECT = 4;
+        const FLASH = 8;
+        const TRANSITION = 32;
+    }
+}
+
+bitflags! {
+    pub struct ClimateFeatures: u32 {
+        const TARGET_TEMPERATURE = 1;
+        const TARGET_TEMPERATURE_RANGE = 2;
+        const TARGET_HUMIDITY = 4;
+        const FAN_MODE = 8;
+        const PRESET_MODE = 16;
+        const SWING_MODE = 32;
+        const AUX_HEAT = 64;
+        const TURN_OFF = 128;
+        const TURN_ON = 256;
+        const SWING_HORIZONTAL_MODE = 512;
+    }
+}
+
+bitflags! {
+    pub struct CoverFeatures: u32 {
+        const OPEN = 1;
+        const CLOSE = 2;
+        const SET_POSITION = 4;
+        const STOP = 8;
+        const OPEN_TILT = 16;
+        const CLOSE_TILT = 32;
+        const STOP_TILT = 64;
+        const SET_TILT_POSITION = 128;
+    }
+}
+
+bitflags! {
+    pub struct MediaPlayerFeatures: u32 {
+        const PAUSE = 1;
+        const SEEK = 2;
+        const VOLUME_SET = 4;
+        const VOLUME_MUTE = 8;
+        const PREVIOUS_TRACK = 16;
+        const NEXT_TRACK = 32;
+        const TURN_ON = 128;
+        const TURN_OFF = 256;
+        const PLAY_MEDIA = 512;
+        const VOLUME_STEP = 1024;
+        const SELECT_SOURCE = 2048;
+        const STOP = 4096;
+        const CLEAR_PLAYLIST = 8192;
+        const PLAY = 16384;
+        const SHUFFLE_SET = 32768;
+        const SELECT_SOUND_MODE = 65536;
+        const BROWSE_MEDIA = 131_072;
+        const REPEAT_SET = 262_144;
+        const GROUPING = 524_288;
+        const MEDIA_ANNOUNCE = 1_048_576;
+        const MEDIA_ENQUEUE = 2_097_152;
+    }
+}
+
+bitflags! {
+    pub struct LockFeatures: u32 {
+        const OPEN = 1;
+    }
+}
+
+bitflags! {
+    pub struct FanFeatures: u32 {
+        const SET_SPEED = 1;
+        const OSCILLATE = 2;
+        const DIRECTION = 4;
+        const PRESET_MODE = 8;
+        const TURN_OFF = 16;
+        const TURN_ON = 32;
+    }
+}
+
+/// Options of `Light::turn_on`, every field left as `None` keeps its current value.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LightTurnOn {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness_pct: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rgb_color: Option<[u8; 3]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_temp_kelvin: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub effect: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serde_helpers::serialize_seconds"
+    )]
+    pub transition: Option<Duration>,
+}
+
+#[derive(Serialize)]
+struct LightTurnOff {
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serde_helpers::serialize_seconds"
+    )]
+    transition: Option<Duration>,
+}
+
+impl Light {
+    pub fn is_on(&self) -> bool {
+        self.state == "on"
+    }
+
+    /// Brightness from 0 to 255, `None` while the light is off.
+    pub fn brightness(&self) -> Option<u8> {
+        self.attribute("brightness")
+    }
+
+    pub fn rgb_color(&self) -> Option<[u8; 3]> {
+        self.attribute("rgb_color")
+    }
+
+    pub fn color_temp_kelvin(&self) -> Option<u32> {
+        self.attribute("color_temp_kelvin")
+    }
+
+    pub fn color_mode(&self) -> Option<&str> {
+        self.attribute_str("color_mode")
+    }
+
+    pub fn supported_color_modes(&self) -> Vec<String> {
+        self.attribute("supported_color_modes").unwrap_or_default()
+    }
+
+    pub fn effect(&self) -> Option<&str> {
+        self.attribute_str("effect")
+    }
+
+    pub fn effect_list(&self) -> Vec<String> {
+        self.attribute("effect_list").unwrap_or_default()
+    }
+
+    pub fn features(&self) -> LightFeatures {
+        LightFeatures::from_bits_truncate(self.supported_features())
+    }
+
+    pub async fn turn_on(
+        &self,
+        rest: &Rest,
+        options: LightTurnOn,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "turn_on", json!(options)).await
+    }
+
+    pub async fn turn_off(
+        &self,
+        rest: &Rest,
+        transition: Option<Duration>,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "turn_off", json!(LightTurnOff { transition })).await
+    }
+
+    pub async fn toggle(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "toggle", json!({})).await
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HvacMode {
+    Off,
+    Heat,
+    Cool,
+    HeatCool,
+    Auto,
+    Dry,
+    FanOnly,
+}
+
+impl Climate {
+    /// The mode the thermostat is set to, `None` while it is unavailable.
+    pub fn hvac_mode(&self) -> Option<HvacMode> {
+        parse_state(&self.state)
+    }
+
+    pub fn hvac_modes(&self) -> Vec<HvacMode> {
+        self.attribute("hvac_modes").unwrap_or_default()
+    }
+
+    /// What the device is currently doing, such as `heating` or `idle`.
+    pub fn hvac_action(&self) -> Option<&str> {
+        self.attribute_str("hvac_action")
+    }
+
+    pub fn current_temperature(&self) -> Option<f64> {
+        self.attribute("current_temperature")
+    }
+
+    pub fn target_temperature(&self) -> Option<f64> {
+        self.attribute("temperature")
+    }
+
+    /// The low and high ends of the target range in `heat_cool` mode.
+    pub fn target_temperature_range(&self) -> Option<(f64, f64)> {
+        Some((
+            self.attribute("target_temp_low")?,
+            self.attribute("target_temp_high")?,
+        ))
+    }
+
+    pub fn preset_mode(&self) -> Option<&str> {
+        self.attribute_str("preset_mode")
+    }
+
+    pub fn features(&self) -> ClimateFeatures {
+        ClimateFeatures::from_bits_truncate(self.supported_features())
+    }
+
+    pub async fn set_temperature(
+        &self,
+        rest: &Rest,
+        temperature: f64,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "set_temperature",
+            json!({ "temperature": temperature }),
+        )
+        .await
+    }
+
+    pub async fn set_hvac_mode(
+        &self,
+        rest: &Rest,
+        hvac_mode: HvacMode,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "set_hvac_mode",
+            json!({ "hvac_mode": hvac_mode }),
+        )
+        .await
+    }
+
+    pub async fn set_preset_mode(
+        &self,
+        rest: &Rest,
+        preset_mode: &str,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "set_preset_mode",
+            json!({ "preset_mode": preset_mode }),
+        )
+        .await
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoverState {
+    Open,
+    Opening,
+    Closed,
+    Closing,
+}
+
+impl Cover {
+    pub fn cover_state(&self) -> Option<CoverState> {
+        parse_state(&self.state)
+    }
+
+    /// Position from 0 (closed) to 100 (open), for covers that report it.
+    pub fn current_position(&self) -> Option<u8> {
+        self.attribute("current_position")
+    }
+
+    pub fn current_tilt_position(&self) -> Option<u8> {
+        self.attribute("current_tilt_position")
+    }
+
+    pub fn features(&self) -> CoverFeatures {
+        CoverFeatures::from_bits_truncate(self.supported_features())
+    }
+
+    pub async fn open(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "open_cover", json!({})).await
+    }
+
+    pub async fn close(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "close_cover", json!({})).await
+    }
+
+    pub async fn stop(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "stop_cover", json!({})).await
+    }
+
+    pub async fn set_position(
+        &self,
+        rest: &Rest,
+        position: u8,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "set_cover_position",
+            json!({ "position": position }),
+        )
+        .await
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaPlayerState {
+    Off,
+    On,
+    Idle,
+    Playing,
+    Paused,
+    Standby,
+    Buffering,
+}
+
+impl MediaPlayer {
+    pub fn player_state(&self) -> Option<MediaPlayerState> {
+        parse_state(&self.state)
+    }
+
+    /// Volume from 0.0 to 1.0.
+    pub fn volume_level(&self) -> Option<f64> {
+        self.attribute("volume_level")
+    }
+
+    pub fn is_volume_muted(&self) -> bool {
+        self.attribute("is_volume_muted").unwrap_or_default()
+    }
+
+    pub fn media_title(&self) -> Option<&str> {
+        self.attribute_str("media_title")
+    }
+
+    pub fn media_artist(&self) -> Option<&str> {
+        self.attribute_str("media_artist")
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.attribute_str("source")
+    }
+
+    pub fn source_list(&self) -> Vec<String> {
+        self.attribute("source_list").unwrap_or_default()
+    }
+
+    pub fn features(&self) -> MediaPlayerFeatures {
+        MediaPlayerFeatures::from_bits_truncate(self.supported_features())
+    }
+
+    pub async fn play(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "media_play", json!({})).await
+    }
+
+    pub async fn pause(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "media_pause", json!({})).await
+    }
+
+    pub async fn stop(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "media_stop", json!({})).await
+    }
+
+    pub async fn next_track(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "media_next_track", json!({})).await
+    }
+
+    pub async fn previous_track(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "media_previous_track", json!({})).await
+    }
+
+    /// Sets the volume from 0.0 to 1.0.
+    pub async fn set_volume(
+        &self,
+        rest: &Rest,
+        volume_level: f64,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "volume_set",
+            json!({ "volume_level": volume_level }),
+        )
+        .await
+    }
+
+    pub async fn mute(&self, rest: &Rest, muted: bool) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "volume_mute",
+            json!({ "is_volume_muted": muted }),
+        )
+        .await
+    }
+}
+
+impl Sensor {
+    /// The state as a number, `None` for text sensors and while the sensor is unavailable.
+    pub fn value(&self) -> Option<f64> {
+        self.numeric_state()
+    }
+
+    /// Such as `measurement` or `total_increasing`.
+    pub fn state_class(&self) -> Option<&str> {
+        self.attribute_str("state_class")
+    }
+}
+
+impl BinarySensor {
+    pub fn is_on(&self) -> bool {
+        self.state == "on"
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockState {
+    Locked,
+    Locking,
+    Unlocked,
+    Unlocking,
+    Jammed,
+    Open,
+    Opening,
+}
+
+impl Lock {
+    pub fn lock_state(&self) -> Option<LockState> {
+        parse_state(&self.state)
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.lock_state() == Some(LockState::Locked)
+    }
+
+    pub fn features(&self) -> LockFeatures {
+        LockFeatures::from_bits_truncate(self.supported_features())
+    }
+
+    pub async fn lock(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "lock", json!({})).await
+    }
+
+    pub async fn unlock(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "unlock", json!({})).await
+    }
+
+    /// Unlatches the door, for locks with `LockFeatures::OPEN`.
+    pub async fn open(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "open", json!({})).await
+    }
+}
+
+impl Fan {
+    pub fn is_on(&self) -> bool {
+        self.state == "on"
+    }
+
+    /// Speed from 0 to 100.
+    pub fn percentage(&self) -> Option<u8> {
+        self.attribute("percentage")
+    }
+
+    pub fn oscillating(&self) -> Option<bool> {
+        self.attribute("oscillating")
+    }
+
+    /// `forward` or `reverse`.
+    pub fn direction(&self) -> Option<&str> {
+        self.attribute_str("direction")
+    }
+
+    pub fn preset_mode(&self) -> Option<&str> {
+        self.attribute_str("preset_mode")
+    }
+
+    pub fn features(&self) -> FanFeatures {
+        FanFeatures::from_bits_truncate(self.supported_features())
+    }
+
+    pub async fn turn_on(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "turn_on", json!({})).await
+    }
+
+    pub async fn turn_off(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "turn_off", json!({})).await
+    }
+
+    pub async fn set_percentage(
+        &self,
+        rest: &Rest,
+        percentage: u8,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "set_percentage",
+            json!({ "percentage": percentage }),
+        )
+        .await
+    }
+
+    pub async fn oscillate(
+        &self,
+        rest: &Rest,
+        oscillating: bool,
+    ) -> Result<Vec<StateObject>, errors::Error> {
+        call(
+            self,
+            rest,
+            "oscillate",
+            json!({ "oscillating": oscillating }),
+        )
+        .await
+    }
+}
+
+impl Switch {
+    pub fn is_on(&self) -> bool {
+        self.state == "on"
+    }
+
+    pub async fn turn_on(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "turn_on", json!({})).await
+    }
+
+    pub async fn turn_off(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "turn_off", json!({})).await
+    }
+
+    pub async fn toggle(&self, rest: &Rest) -> Result<Vec<StateObject>, errors::Error> {
+        call(self, rest, "toggle", json!({})).await
+    }
+}
+
+/// Calls `service` of the domain of `entity` on that entity.
+async fn call(
+    entity: &StateObject,
+    rest: &Rest,
+    service: &str,
+    mut service_data: serde_json::Value,
+) -> Result<Vec<StateObject>, errors::Error> {
+    service_data["entity_id"] = entity.entity_id.clone().into();
+    rest.service_call(
+        entity.domain().to_string(),
+        service.to_string(),
+        Some(service_data),
+    )
+    .await
+}
+
+/// Parses a state such as `heat_cool` into its enum, `None` for `unavailable` and
+/// `unknown`.
+fn parse_state<T: serde::de::DeserializeOwned>(state: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(state.to_string())).ok()
+}
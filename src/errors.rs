@@ -1,38 +1,97 @@
+use reqwest::StatusCode;
 use std::fmt;
 
 #[derive(Debug)]
 pub enum Error {
     Request(reqwest::Error),
+    /// A request or connection took longer than the configured timeout.
+    Timeout(Box<dyn std::error::Error + Send + Sync>),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// HA answered with a body that does not match the expected type, the raw body is kept.
+    Decode(serde_json::Error, String),
+    Tls(native_tls::Error),
+    WebSocket(tokio_tungstenite::tungstenite::Error),
+    /// A websocket command failed, with the code and message HA returned.
+    Command(String, String),
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    /// Any other non 2xx status, with the message HA returned.
+    Http(StatusCode, String),
     HaApi(String),
     Config(String),
-    Refresh(),
-    NoAuth(),
-    PoisonError(
-        std::sync::PoisonError<
-            std::sync::RwLockReadGuard<'static, std::option::Option<crate::Token>>,
-        >,
-    ),
+    /// Service data that does not match the schema of the service, caught before calling it.
+    InvalidServiceData(String),
+    /// A typed view of one domain was built from an entity of another, with the expected
+    /// domain and the entity id.
+    WrongDomain(String, String),
+    /// A string that is not a valid `domain.object_id` entity id.
+    InvalidEntityId(String),
+    Refresh,
+    RefreshTokenRevoked,
+    NoAuth,
+    StateMismatch,
+}
+
+impl Error {
+    /// Builds the error for a non 2xx `status` answered with `message`.
+    pub(crate) fn from_status(status: StatusCode, message: String) -> Self {
+        match status {
+            StatusCode::BAD_REQUEST => Error::BadRequest(message),
+            StatusCode::UNAUTHORIZED => Error::Unauthorized(message),
+            StatusCode::FORBIDDEN => Error::Forbidden(message),
+            StatusCode::NOT_FOUND => Error::NotFound(message),
+            status => Error::Http(status, message),
+        }
+    }
+
+    /// The HTTP status HA answered with, if the error came from one.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::BadRequest(_) => Some(StatusCode::BAD_REQUEST),
+            Error::Unauthorized(_) => Some(StatusCode::UNAUTHORIZED),
+            Error::Forbidden(_) => Some(StatusCode::FORBIDDEN),
+            Error::NotFound(_) => Some(StatusCode::NOT_FOUND),
+            Error::Http(status, _) => Some(*status),
+            Error::Request(inner) => inner.status(),
+            _ => None,
+        }
+    }
 }
 
 impl From<reqwest::Error> for Error {
     fn from(error: reqwest::Error) -> Self {
-        Error::Request(error)
+        if error.is_timeout() {
+            Error::Timeout(Box::new(error))
+        } else {
+            Error::Request(error)
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
+
+impl From<native_tls::Error> for Error {
+    fn from(error: native_tls::Error) -> Self {
+        Error::Tls(error)
     }
 }
 
-impl
-    From<
-        std::sync::PoisonError<
-            std::sync::RwLockReadGuard<'static, std::option::Option<crate::Token>>,
-        >,
-    > for Error
-{
-    fn from(
-        error: std::sync::PoisonError<
-            std::sync::RwLockReadGuard<'static, std::option::Option<crate::Token>>,
-        >,
-    ) -> Self {
-        Error::PoisonError(error)
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(error: tokio_tungstenite::tungstenite::Error) -> Self {
+        Error::WebSocket(error)
     }
 }
 
@@ -40,11 +99,47 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Request(inner) => write!(f, "{}", inner),
+            Error::Timeout(inner) => write!(f, "Timed out talking to HA: {}", inner),
+            Error::Io(inner) => write!(f, "{}", inner),
+            Error::Json(inner) => write!(f, "{}", inner),
+            Error::Decode(inner, body) => {
+                write!(
+                    f,
+                    "Could not decode the HA response: {} Body: {}",
+                    inner, body
+                )
+            }
+            Error::Tls(inner) => write!(f, "{}", inner),
+            Error::WebSocket(inner) => write!(f, "{}", inner),
+            Error::Command(code, message) => {
+                write!(
+                    f,
+                    "HA websocket command failed Code: {} Message: {}",
+                    code, message
+                )
+            }
+            Error::BadRequest(message) => write!(f, "HA rejected the request: {}", message),
+            Error::Unauthorized(message) => write!(f, "HA rejected the credentials: {}", message),
+            Error::Forbidden(message) => write!(f, "HA denied access: {}", message),
+            Error::NotFound(message) => write!(f, "HA could not find it: {}", message),
+            Error::Http(status, message) => {
+                write!(f, "HA responded with {}: {}", status, message)
+            }
             Error::Config(inner) => write!(f, "{}", inner),
             Error::HaApi(inner) => write!(f, "{}", inner),
-            Error::PoisonError(inner) => write!(f, "{}", inner),
-            Error::Refresh() => write!(f, "Tried to refresh a long lived access token"),
-            Error::NoAuth() => write!(f, "There are no Authentication Credentials"),
+            Error::InvalidServiceData(message) => write!(f, "Invalid service data: {}", message),
+            Error::InvalidEntityId(entity_id) => write!(f, "Invalid entity id: {}", entity_id),
+            Error::WrongDomain(domain, entity_id) => {
+                write!(f, "Expected a {} entity, got {}", domain, entity_id)
+            }
+            Error::Refresh => write!(f, "Tried to refresh a long lived access token"),
+            Error::RefreshTokenRevoked => {
+                write!(f, "The refresh token was revoked by Home Assistant")
+            }
+            Error::NoAuth => write!(f, "There are no Authentication Credentials"),
+            Error::StateMismatch => {
+                write!(f, "The login redirect did not carry the expected state")
+            }
         }
     }
 }
@@ -53,6 +148,12 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::Request(inner) => Some(inner),
+            Error::Timeout(inner) => Some(inner.as_ref()),
+            Error::Io(inner) => Some(inner),
+            Error::Json(inner) => Some(inner),
+            Error::Decode(inner, _) => Some(inner),
+            Error::Tls(inner) => Some(inner),
+            Error::WebSocket(inner) => Some(inner),
             _ => None,
         }
     }
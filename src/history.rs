@@ -0,0 +1,176 @@
+use crate::errors;
+use crate::request;
+use crate::types::{EntityId, HistoryState};
+use chrono::{DateTime, Utc};
+use reqwest::Method;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Builder for `/api/history/period`, created by `Rest::history`.
+///
+/// ```no_run
+/// # async fn example(rest: homeassistant::rest::Rest) -> Result<(), homeassistant::errors::Error> {
+/// let history = rest
+///     .history()
+///     .entity_id("sensor.outside".parse()?)
+///     .start(chrono::Utc::now() - chrono::Duration::hours(6))
+///     .minimal_response()
+///     .send()
+///     .await?;
+/// for point in &history["sensor.outside"] {
+///     println!("{} {}", point.last_changed, point.state);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct HistoryQuery {
+    ha_client: crate::HomeAssistantAPI,
+    entity_ids: Vec<EntityId>,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    minimal_response: bool,
+    no_attributes: bool,
+    significant_changes_only: Option<bool>,
+}
+
+/// A row as HA sends it, minimal rows after the first one of an entity only carry the state
+/// and when it changed.
+#[derive(Deserialize)]
+struct HistoryRow {
+    #[serde(default)]
+    entity_id: Option<String>,
+    state: String,
+    #[serde(default)]
+    attributes: Option<HashMap<String, serde_json::Value>>,
+    last_changed: DateTime<Utc>,
+    #[serde(default)]
+    last_updated: Option<DateTime<Utc>>,
+}
+
+impl HistoryQuery {
+    pub(crate) fn new(ha_client: crate::HomeAssistantAPI) -> Self {
+        Self {
+            ha_client,
+            entity_ids: Vec::new(),
+            start: None,
+            end: None,
+            minimal_response: false,
+            no_attributes: false,
+            significant_changes_only: None,
+        }
+    }
+
+    /// Adds an entity to query, HA requires at least one.
+    pub fn entity_id(mut self, entity_id: EntityId) -> Self {
+        self.entity_ids.push(entity_id);
+        self
+    }
+
+    pub fn entity_ids(mut self, entity_ids: impl IntoIterator<Item = EntityId>) -> Self {
+        self.entity_ids.extend(entity_ids);
+        self
+    }
+
+    /// Start of the period, HA defaults to one day ago.
+    pub fn start(mut self, start: DateTime<Utc>) -> Self {
+        self.start = Some(start);
+        self
+    }
+
+    /// End of the period, HA defaults to one day after `start`.
+    pub fn end(mut self, end: DateTime<Utc>) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    /// Only returns the attributes of the first state of each entity, later states only
+    /// carry their state and `last_changed`.
+    pub fn minimal_response(mut self) -> Self {
+        self.minimal_response = true;
+        self
+    }
+
+    /// Leaves out the attributes of every state.
+    pub fn no_attributes(mut self) -> Self {
+        self.no_attributes = true;
+        self
+    }
+
+    /// Whether to skip attribute-only changes of entities such as sensors, HA does so by
+    /// default.
+    pub fn significant_changes_only(mut self, significant_changes_only: bool) -> Self {
+        self.significant_changes_only = Some(significant_changes_only);
+        self
+    }
+
+    /// The states of every queried entity over the period, oldest first.
+    pub async fn send(self) -> Result<HashMap<String, Vec<HistoryState>>, errors::Error> {
+        if self.entity_ids.is_empty() {
+            return Err(errors::Error::Config(String::from(
+                "A history query needs at least one entity id",
+            )));
+        }
+
+        let mut endpoint = String::from("/api/history/period");
+        if let Some(start) = self.start {
+            endpoint = format!("{}/{}", endpoint, format_timestamp(start));
+        }
+
+        let entity_ids = self
+            .entity_ids
+            .iter()
+            .map(EntityId::as_str)
+            .collect::<Vec<_>>()
+            .join(",");
+        let groups: Vec<Vec<serde_json::Value>> = self
+            .ha_client
+            .request(Method::GET, &endpoint, |mut request| {
+                request = request.query(&[("filter_entity_id", &entity_ids)]);
+                if let Some(end) = self.end {
+                    request = request.query(&[("end_time", format_timestamp(end))]);
+                }
+                if self.minimal_response {
+                    request = request.query(&[("minimal_response", "")]);
+                }
+                if self.no_attributes {
+                    request = request.query(&[("no_attributes", "")]);
+                }
+                match self.significant_changes_only {
+                    Some(true) => request.query(&[("significant_changes_only", "1")]),
+                    Some(false) => request.query(&[("significant_changes_only", "0")]),
+                    None => request,
+                }
+            })
+            .await?;
+
+        let mut history = HashMap::new();
+        for group in groups {
+            let mut rows = Vec::with_capacity(group.len());
+            for row in group {
+                rows.push(request::decode_value::<HistoryRow>(row)?);
+            }
+            let entity_id = match rows.first().and_then(|row| row.entity_id.clone()) {
+                Some(entity_id) => entity_id,
+                None => continue,
+            };
+            let states = rows
+                .into_iter()
+                .map(|row| HistoryState {
+                    state: row.state,
+                    attributes: row.attributes,
+                    last_changed: row.last_changed,
+                    // minimal rows are state changes, so they were updated when they changed
+                    last_updated: row.last_updated.unwrap_or(row.last_changed),
+                })
+                .collect();
+            history.insert(entity_id, states);
+        }
+
+        Ok(history)
+    }
+}
+
+pub(crate) fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
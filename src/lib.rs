@@ -1,22 +1,52 @@
 use crate::types::*;
-use std::convert::TryFrom;
-use std::sync::{Arc, RwLock, Weak};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time;
+use tokio::sync::{Mutex, RwLock};
 
+pub mod client;
+pub mod domains;
 pub mod errors;
+pub mod history;
+pub mod login_flow;
+pub mod mirror;
 pub mod native_app;
+pub mod oauth;
+pub mod registry;
+mod request;
 pub mod rest;
+mod serde_helpers;
+pub mod services;
+pub mod statistics;
+pub mod token_store;
+pub mod trigger;
 pub mod types;
+pub mod websocket;
 
-#[derive(Debug)]
+/// Handle to a Home Assistant instance, clones are cheap and share the same token.
+#[derive(Debug, Clone)]
 pub struct HomeAssistantAPI {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
     instance_url: String,
-    token: Token,
     client_id: String,
-    self_reference: Weak<RwLock<Self>>,
+    http: reqwest::Client,
+    tls: client::TlsConfig,
+    websocket: client::WebSocketConfig,
+    token: RwLock<Token>,
+    token_store: RwLock<Option<Arc<dyn token_store::TokenStore>>>,
+    // held while saving so concurrent token changes reach the store in order
+    persist_lock: Mutex<()>,
+    // held while refreshing so concurrent requests wait for a single refresh
+    refresh_lock: Mutex<()>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Token {
     Oauth(OAuthToken),
     LongLived(LongLivedToken),
@@ -28,16 +58,19 @@ impl Token {
         match self {
             Token::Oauth(token) => Ok(token.token.clone()),
             Token::LongLived(token) => Ok(token.token.clone()),
-            Token::None => Err(errors::Error::NoAuth()),
+            Token::None => Err(errors::Error::NoAuth),
         }
     }
 
     pub fn need_refresh(&self) -> bool {
         match self {
             Token::Oauth(token) => {
-                match time::SystemTime::now().duration_since(token.token_expiration) {
-                    Ok(sec_left) => sec_left > time::Duration::from_secs(10),
-                    Err(_) => false,
+                match token
+                    .token_expiration
+                    .duration_since(time::SystemTime::now())
+                {
+                    Ok(sec_left) => sec_left < time::Duration::from_secs(10),
+                    Err(_) => true,
                 }
             }
             Token::LongLived(_) => false,
@@ -46,76 +79,275 @@ impl Token {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OAuthToken {
     token: String,
     token_expiration: std::time::SystemTime,
     refresh_token: String,
 }
 
-#[derive(Debug, Clone)]
+impl OAuthToken {
+    pub fn refresh_token(&self) -> &str {
+        &self.refresh_token
+    }
+
+    pub fn expiration(&self) -> time::SystemTime {
+        self.token_expiration
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LongLivedToken {
     token: String,
 }
 
 impl HomeAssistantAPI {
-    pub fn new(instance_url: String, client_id: String) -> Arc<RwLock<Self>> {
-        let token = Token::None;
-        let ret = Arc::new(RwLock::new(Self {
-            instance_url,
-            token,
-            client_id,
-            self_reference: Weak::new(),
-        }));
+    pub fn new(instance_url: String, client_id: String) -> Self {
+        Self::builder(instance_url, client_id)
+            .build()
+            .expect("the default client configuration is valid")
+    }
+
+    pub fn builder(instance_url: String, client_id: String) -> client::ClientBuilder {
+        client::ClientBuilder::new(instance_url, client_id)
+    }
+
+    pub fn instance_url(&self) -> &str {
+        &self.inner.instance_url
+    }
+
+    pub fn client_id(&self) -> &str {
+        &self.inner.client_id
+    }
 
-        ret.write().unwrap().self_reference = Arc::downgrade(&ret);
+    pub(crate) fn http_client(&self) -> &reqwest::Client {
+        &self.inner.http
+    }
+
+    /// Persists every token change to `store`, a token already saved in the store
+    /// replaces the current one so sessions survive restarts.
+    pub async fn set_token_store(
+        &self,
+        store: Box<dyn token_store::TokenStore>,
+    ) -> Result<(), errors::Error> {
+        let store: Arc<dyn token_store::TokenStore> = Arc::from(store);
+        let loading = store.clone();
+        let saved = blocking(move || loading.load()).await?;
+
+        let mut token = self.inner.token.write().await;
+        let mut token_store = self.inner.token_store.write().await;
+        if let Some(saved) = saved {
+            *token = saved;
+        }
+        *token_store = Some(store);
+        Ok(())
+    }
 
-        ret
+    /// Replaces the token and saves it to the token store, the new token is used even
+    /// when saving it fails.
+    pub async fn set_token(&self, token: Token) -> Result<(), errors::Error> {
+        *self.inner.token.write().await = token;
+        self.persist_token().await
     }
 
-    pub fn set_oauth_token(
-        &mut self,
+    /// Saves the current token off the runtime and without holding the token lock, so
+    /// requests are not held up by the disk.
+    async fn persist_token(&self) -> Result<(), errors::Error> {
+        let store = match self.inner.token_store.read().await.as_ref() {
+            Some(store) => store.clone(),
+            None => return Ok(()),
+        };
+        let _persisting = self.inner.persist_lock.lock().await;
+        // a token set while waiting for the lock is saved now, so the last one wins
+        let token = self.token().await;
+        blocking(move || store.save(&token)).await
+    }
+
+    pub async fn set_oauth_token(
+        &self,
         access_token: String,
         expires_in: u32,
         refresh_token: String,
-    ) {
+    ) -> Result<(), errors::Error> {
         let oauth = OAuthToken {
             token: access_token,
             token_expiration: time::SystemTime::now()
                 + time::Duration::from_secs(expires_in as u64),
             refresh_token,
         };
-        self.token = Token::Oauth(oauth);
+        self.set_token(Token::Oauth(oauth)).await
+    }
+
+    pub async fn set_long_lived_token(&self, token: String) -> Result<(), errors::Error> {
+        self.set_token(Token::LongLived(LongLivedToken { token }))
+            .await
+    }
+
+    pub async fn token(&self) -> Token {
+        self.inner.token.read().await.clone()
     }
 
-    pub fn set_long_lived_token(&mut self, token: String) {
-        self.token = Token::LongLived(LongLivedToken { token });
+    pub async fn refresh_oauth_token(&self) -> Result<(), errors::Error> {
+        let _refreshing = self.inner.refresh_lock.lock().await;
+        self.refresh_locked().await
     }
 
-    pub async fn refresh_oauth_token(&mut self) -> Result<(), errors::Error> {
-        let refresh_token = String::from("test");
+    /// Returns the current access token, refreshing it first when it is about to expire.
+    pub(crate) async fn valid_access_token(&self) -> Result<String, errors::Error> {
+        {
+            let token = self.inner.token.read().await;
+            if !token.need_refresh() {
+                return token.as_string();
+            }
+        }
 
-        let response = reqwest::Client::new()
-            .post(format!("{}/auth/token", self.instance_url).as_str())
-            .query(&[
-                ("grant_type", "refresh_token"),
-                ("client_id", &self.client_id),
-                ("refresh_token", refresh_token.as_str()),
-            ])
+        let _refreshing = self.inner.refresh_lock.lock().await;
+        // another task may have refreshed the token while this one was waiting
+        if self.inner.token.read().await.need_refresh() {
+            self.refresh_locked().await?;
+        }
+        self.inner.token.read().await.as_string()
+    }
+
+    async fn refresh_locked(&self) -> Result<(), errors::Error> {
+        let refresh_token = match &*self.inner.token.read().await {
+            Token::Oauth(token) => token.refresh_token.clone(),
+            Token::LongLived(_) => return Err(errors::Error::Refresh),
+            Token::None => return Err(errors::Error::NoAuth),
+        };
+
+        let request = RefreshAccessTokenRequest {
+            grant_type: "refresh_token".to_string(),
+            refresh_token: refresh_token.clone(),
+            client_id: self.inner.client_id.clone(),
+        };
+        let resp = self
+            .inner
+            .http
+            .post(format!("{}/auth/token", self.inner.instance_url).as_str())
+            .form(&request)
             .send()
             .await?;
 
-        let refresh_token_resp: RefreshAccessTokenResponse = response.json().await?;
-        self.set_oauth_token(
-            refresh_token_resp.access_token,
-            refresh_token_resp.expires_in,
-            refresh_token,
-        );
-        Ok(())
+        match resp.status().as_str() {
+            "200" => {
+                let refresh_token_resp = resp.json::<RefreshAccessTokenResponse>().await?;
+                self.set_oauth_token(
+                    refresh_token_resp.access_token,
+                    refresh_token_resp.expires_in,
+                    refresh_token,
+                )
+                .await?;
+                Ok(())
+            }
+            _ => {
+                let status = resp.status();
+                let error = resp.json::<GetAccessTokenError>().await?;
+                match error.error.as_str() {
+                    // HA answers with invalid_grant once the refresh token is revoked or unknown
+                    "invalid_grant" => Err(errors::Error::RefreshTokenRevoked),
+                    _ => Err(errors::Error::from_status(
+                        status,
+                        format!(
+                            "Error refreshing access token from HA Error: {} Details: {}",
+                            error.error, error.error_description
+                        ),
+                    )),
+                }
+            }
+        }
+    }
+
+    /// Revokes the refresh token of the current OAuth token and forgets it, this logs the
+    /// client out of HA.
+    pub async fn revoke_token(&self) -> Result<(), errors::Error> {
+        let refresh_token = match &*self.inner.token.read().await {
+            Token::Oauth(token) => token.refresh_token.clone(),
+            Token::LongLived(_) => {
+                return Err(errors::Error::Config(String::from(
+                    "Long lived tokens have to be removed with delete_refresh_token",
+                )))
+            }
+            Token::None => return Err(errors::Error::NoAuth),
+        };
+
+        let request = RevokeTokenRequest {
+            token: refresh_token.clone(),
+            action: None,
+        };
+        let resp = self
+            .inner
+            .http
+            .post(format!("{}/auth/revoke", self.inner.instance_url).as_str())
+            .form(&request)
+            .send()
+            .await?;
+
+        // HA versions before /auth/revoke existed revoke through the token endpoint
+        let resp = if resp.status() == reqwest::StatusCode::NOT_FOUND
+            || resp.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED
+        {
+            let request = RevokeTokenRequest {
+                token: refresh_token,
+                action: Some(String::from("revoke")),
+            };
+            self.inner
+                .http
+                .post(format!("{}/auth/token", self.inner.instance_url).as_str())
+                .form(&request)
+                .send()
+                .await?
+        } else {
+            resp
+        };
+        // the token is kept when HA did not revoke it, so the caller can retry
+        request::check_status(resp).await?;
+
+        self.set_token(Token::None).await
+    }
+
+    /// Lists the refresh tokens, including long lived tokens, of the logged in user.
+    pub async fn refresh_tokens(&self) -> Result<Vec<RefreshTokenInfo>, errors::Error> {
+        self.get_websocket_client()
+            .await?
+            .command(serde_json::json!({ "type": "auth/refresh_tokens" }))
+            .await
+    }
+
+    /// Deletes one of the refresh tokens returned by `refresh_tokens`, which also
+    /// invalidates every access token issued for it.
+    pub async fn delete_refresh_token(&self, refresh_token_id: &str) -> Result<(), errors::Error> {
+        self.get_websocket_client()
+            .await?
+            .command(serde_json::json!({
+                "type": "auth/delete_refresh_token",
+                "refresh_token_id": refresh_token_id,
+            }))
+            .await
+    }
+
+    /// Mints a long lived access token valid for `lifespan_days`, the current token is kept,
+    /// pass the returned token to `set_token` or a `TokenStore` to use it.
+    pub async fn create_long_lived_token(
+        &self,
+        client_name: &str,
+        lifespan_days: u32,
+    ) -> Result<Token, errors::Error> {
+        let token: String = self
+            .get_websocket_client()
+            .await?
+            .command(serde_json::json!({
+                "type": "auth/long_lived_access_token",
+                "client_name": client_name,
+                "lifespan": lifespan_days,
+            }))
+            .await?;
+
+        Ok(Token::LongLived(LongLivedToken { token }))
     }
 
     pub async fn access_token(
-        &mut self,
+        &self,
         code: String,
         client_id: String,
     ) -> Result<GetAccessTokenResponse, errors::Error> {
@@ -124,8 +356,10 @@ impl HomeAssistantAPI {
             code,
             client_id,
         };
-        let resp = reqwest::Client::new()
-            .post(format!("{}/auth/token", self.instance_url).as_str())
+        let resp = self
+            .inner
+            .http
+            .post(format!("{}/auth/token", self.inner.instance_url).as_str())
             .form(&request)
             .send()
             .await?;
@@ -137,40 +371,144 @@ impl HomeAssistantAPI {
                     access_token_resp.access_token.clone(),
                     access_token_resp.expires_in,
                     access_token_resp.refresh_token.clone(),
-                );
+                )
+                .await?;
                 Ok(access_token_resp)
             }
             _ => {
+                let status = resp.status();
                 let error = resp.json::<GetAccessTokenError>().await?;
-                Err(errors::Error::HaApi(format!(
-                    "Error getting access token from HA Error: {} Details: {}",
-                    error.error, error.error_description
-                )))
+                Err(errors::Error::from_status(
+                    status,
+                    format!(
+                        "Error getting access token from HA Error: {} Details: {}",
+                        error.error, error.error_description
+                    ),
+                ))
             }
         }
     }
 
-    pub async fn get_rest_client(&self) -> rest::Rest {
-        match rest::Rest::try_from(self.self_reference.clone()) {
-            Ok(rest) => rest,
-            Err(_) => unreachable!(),
+    /// Binds a loopback listener for the authorization code redirect, open
+    /// `authorize_url()` of the returned listener in a browser and pass it to `login`.
+    pub async fn login_listener(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<oauth::LoginListener, errors::Error> {
+        oauth::LoginListener::bind(&self.inner.instance_url, &self.inner.client_id, addr).await
+    }
+
+    /// Waits for the browser redirect and exchanges the received code for an access token.
+    pub async fn login(
+        &self,
+        listener: oauth::LoginListener,
+    ) -> Result<GetAccessTokenResponse, errors::Error> {
+        let code = listener.wait_for_code().await?;
+        let client_id = self.inner.client_id.clone();
+        self.access_token(code, client_id).await
+    }
+
+    /// Starts a headless login flow with the built in `homeassistant` auth provider.
+    pub async fn login_flow(&self) -> Result<login_flow::LoginFlow, errors::Error> {
+        login_flow::LoginFlow::start(
+            self.clone(),
+            self.inner.client_id.clone(),
+            (String::from("homeassistant"), None),
+        )
+        .await
+    }
+
+    /// Logs in with a username and password through the login flow, `mfa_code` is only
+    /// needed when the user has a multi factor auth module such as TOTP enabled.
+    pub async fn login_with_credentials(
+        &self,
+        username: &str,
+        password: &str,
+        mfa_code: Option<&str>,
+    ) -> Result<GetAccessTokenResponse, errors::Error> {
+        let mut flow = self.login_flow().await?;
+        let mut step = flow.submit_credentials(username, password).await?;
+
+        loop {
+            let form = match step {
+                LoginFlowStep::Form(form) => form,
+                LoginFlowStep::CreateEntry(result) => {
+                    let code = result.result.clone();
+                    let client_id = self.inner.client_id.clone();
+                    return self.access_token(code, client_id).await;
+                }
+                LoginFlowStep::Abort(abort) => {
+                    return Err(errors::Error::HaApi(format!(
+                        "HA aborted the login flow Reason: {}",
+                        abort.reason
+                    )))
+                }
+            };
+
+            if let Some(error) = form.errors.get("base") {
+                return Err(errors::Error::HaApi(format!(
+                    "Error logging in to HA Error: {}",
+                    error
+                )));
+            }
+
+            step = match form.step_id {
+                LoginFlowStepId::SelectMfaModule => {
+                    let module_id = form
+                        .data_schema
+                        .iter()
+                        .filter_map(|field| field.options.as_ref())
+                        .flatten()
+                        .map(|(id, _)| id.clone())
+                        .next()
+                        .ok_or_else(|| {
+                            errors::Error::HaApi(String::from("HA offered no mfa module"))
+                        })?;
+                    flow.select_mfa_module(&module_id).await?
+                }
+                LoginFlowStepId::Mfa => {
+                    let code = mfa_code.ok_or_else(|| {
+                        errors::Error::Config(String::from("HA requires an mfa code for this user"))
+                    })?;
+                    flow.submit_mfa_code(code).await?
+                }
+                _ => {
+                    return Err(errors::Error::HaApi(String::from(
+                        "Unexpected step in the HA login flow",
+                    )))
+                }
+            };
         }
     }
 
+    pub async fn get_rest_client(&self) -> rest::Rest {
+        rest::Rest::from(self.clone())
+    }
+
+    /// Opens a websocket connection, authenticated with the current token.
+    pub async fn get_websocket_client(&self) -> Result<websocket::WebSocket, errors::Error> {
+        websocket::WebSocket::connect(self).await
+    }
+
     pub async fn get_native_client_from_config(
         &self,
         config: native_app::NativeAppConfig,
     ) -> native_app::NativeApp {
-        match native_app::NativeApp::from_config(config, self.self_reference.clone()) {
-            Ok(native_app) => native_app,
-            Err(_) => unreachable!(),
-        }
+        native_app::NativeApp::from_config(config, self.clone())
     }
 
     pub async fn get_native_client(&self) -> native_app::NativeApp {
-        match native_app::NativeApp::new(self.self_reference.clone()) {
-            Ok(native_app) => native_app,
-            Err(_) => unreachable!(),
-        }
+        native_app::NativeApp::new(self.clone())
     }
 }
+
+/// Runs blocking work such as token store IO on the blocking thread pool.
+async fn blocking<T, F>(work: F) -> Result<T, errors::Error>
+where
+    F: FnOnce() -> Result<T, errors::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| errors::Error::Io(std::io::Error::other(e)))?
+}
@@ -0,0 +1,120 @@
+use crate::errors;
+use crate::request;
+use crate::types;
+use std::collections::HashMap;
+
+/// Client for the `/auth/login_flow` api, used to log in without a browser.
+///
+/// Every submitted step is answered with the next `LoginFlowStep`, once the flow reaches
+/// `CreateEntry` its `result` is the authorization code for `HomeAssistantAPI::access_token`.
+#[derive(Debug)]
+pub struct LoginFlow {
+    ha_client: crate::HomeAssistantAPI,
+    step: types::LoginFlowStep,
+}
+
+impl LoginFlow {
+    /// Starts a login flow against the auth provider `handler`, e.g. `("homeassistant", None)`.
+    pub async fn start(
+        ha_client: crate::HomeAssistantAPI,
+        redirect_uri: String,
+        handler: (String, Option<String>),
+    ) -> Result<Self, errors::Error> {
+        let request = types::LoginFlowRequest {
+            client_id: ha_client.client_id().to_string(),
+            handler,
+            redirect_uri,
+        };
+        let resp = ha_client
+            .http_client()
+            .post(format!("{}/auth/login_flow", ha_client.instance_url()).as_str())
+            .json(&request)
+            .send()
+            .await?;
+        let step = parse_step(resp).await?;
+
+        Ok(Self { ha_client, step })
+    }
+
+    pub fn step(&self) -> &types::LoginFlowStep {
+        &self.step
+    }
+
+    pub fn flow_id(&self) -> &str {
+        match &self.step {
+            types::LoginFlowStep::Form(form) => &form.flow_id,
+            types::LoginFlowStep::CreateEntry(result) => &result.flow_id,
+            types::LoginFlowStep::Abort(abort) => &abort.flow_id,
+        }
+    }
+
+    /// The authorization code, available once the flow has finished successfully.
+    pub fn code(&self) -> Option<&str> {
+        match &self.step {
+            types::LoginFlowStep::CreateEntry(result) => Some(&result.result),
+            _ => None,
+        }
+    }
+
+    pub async fn submit_credentials(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<&types::LoginFlowStep, errors::Error> {
+        let mut data = HashMap::new();
+        data.insert("username", username);
+        data.insert("password", password);
+        self.submit(data).await
+    }
+
+    pub async fn select_mfa_module(
+        &mut self,
+        module_id: &str,
+    ) -> Result<&types::LoginFlowStep, errors::Error> {
+        let mut data = HashMap::new();
+        data.insert("multi_factor_auth_module", module_id);
+        self.submit(data).await
+    }
+
+    pub async fn submit_mfa_code(
+        &mut self,
+        code: &str,
+    ) -> Result<&types::LoginFlowStep, errors::Error> {
+        let mut data = HashMap::new();
+        data.insert("code", code);
+        self.submit(data).await
+    }
+
+    async fn submit(
+        &mut self,
+        mut data: HashMap<&str, &str>,
+    ) -> Result<&types::LoginFlowStep, errors::Error> {
+        if !matches!(self.step, types::LoginFlowStep::Form(_)) {
+            return Err(errors::Error::HaApi(String::from(
+                "The login flow has already finished",
+            )));
+        }
+
+        data.insert("client_id", self.ha_client.client_id());
+        let endpoint = format!(
+            "{}/auth/login_flow/{}",
+            self.ha_client.instance_url(),
+            self.flow_id()
+        );
+        let resp = self
+            .ha_client
+            .http_client()
+            .post(endpoint.as_str())
+            .json(&data)
+            .send()
+            .await?;
+        self.step = parse_step(resp).await?;
+
+        Ok(&self.step)
+    }
+}
+
+async fn parse_step(resp: reqwest::Response) -> Result<types::LoginFlowStep, errors::Error> {
+    let body = request::check_status(resp).await?.text().await?;
+    request::decode(body)
+}
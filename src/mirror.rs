@@ -0,0 +1,262 @@
+use crate::errors;
+use crate::types::{CompressedState, CompressedStateDiff, EntitiesDiff, StateObject};
+use crate::websocket::{Delivery, Subscription};
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{oneshot, watch};
+
+type Entities = RwLock<HashMap<String, Entity>>;
+
+/// Local copy of the entity states, kept up to date through `subscribe_entities`.
+///
+/// Clones share the same states, the subscription ends once every clone is dropped.
+#[derive(Debug, Clone)]
+pub struct StateMirror {
+    entities: Arc<Entities>,
+    // dropped with the last clone, which stops the task applying the diffs
+    _stop: Arc<oneshot::Sender<()>>,
+}
+
+/// Receives the state of an entity, as returned by `StateMirror::watch`.
+#[derive(Debug, Clone)]
+pub struct StateWatch {
+    receiver: watch::Receiver<Option<StateObject>>,
+    _watcher: Arc<()>,
+}
+
+impl Deref for StateWatch {
+    type Target = watch::Receiver<Option<StateObject>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+impl DerefMut for StateWatch {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.receiver
+    }
+}
+
+#[derive(Debug)]
+struct Entity {
+    sender: watch::Sender<Option<StateObject>>,
+    // kept so the sender always has a receiver and new watchers can be cloned from it
+    receiver: watch::Receiver<Option<StateObject>>,
+    /// Shared with every `StateWatch` of the entity.
+    watchers: Arc<()>,
+}
+
+impl Entity {
+    fn new() -> Self {
+        let (sender, receiver) = watch::channel(None);
+        Self {
+            sender,
+            receiver,
+            watchers: Arc::new(()),
+        }
+    }
+
+    fn state(&self) -> Option<StateObject> {
+        self.receiver.borrow().clone()
+    }
+
+    fn set(&self, state: Option<StateObject>) {
+        let _ = self.sender.broadcast(state);
+    }
+
+    fn watch(&self) -> StateWatch {
+        StateWatch {
+            receiver: self.receiver.clone(),
+            _watcher: self.watchers.clone(),
+        }
+    }
+
+    /// Entities without a state are only kept while someone watches them.
+    fn is_needed(&self) -> bool {
+        self.receiver.borrow().is_some() || Arc::strong_count(&self.watchers) > 1
+    }
+}
+
+impl StateMirror {
+    /// Waits for the initial states of `subscription`, then applies its diffs in the
+    /// background.
+    pub(crate) async fn start(
+        mut subscription: Subscription<EntitiesDiff>,
+    ) -> Result<Self, errors::Error> {
+        let (stop, stopped) = oneshot::channel();
+        let mirror = Self {
+            entities: Arc::new(RwLock::new(HashMap::new())),
+            _stop: Arc::new(stop),
+        };
+
+        loop {
+            match subscription.next_delivery().await {
+                Some(Delivery::Event(diff)) => {
+                    apply(&mirror.entities, diff?, false);
+                    break;
+                }
+                Some(Delivery::Resubscribed) => continue,
+                None => return Err(tokio_tungstenite::tungstenite::Error::ConnectionClosed.into()),
+            }
+        }
+
+        tokio::spawn(follow(subscription, mirror.entities.clone(), stopped));
+        Ok(mirror)
+    }
+
+    /// The current state of `entity_id`, if it exists.
+    pub fn get(&self, entity_id: &str) -> Option<StateObject> {
+        self.read().get(entity_id).and_then(Entity::state)
+    }
+
+    /// A snapshot of every mirrored state.
+    pub fn states(&self) -> Vec<StateObject> {
+        self.read().values().filter_map(Entity::state).collect()
+    }
+
+    pub fn entity_ids(&self) -> Vec<String> {
+        self.read()
+            .iter()
+            .filter(|(_, entity)| entity.receiver.borrow().is_some())
+            .map(|(entity_id, _)| entity_id.clone())
+            .collect()
+    }
+
+    /// Watches the state of `entity_id`, which is `None` while the entity does not exist.
+    /// Entities that do not exist are only tracked while they are watched.
+    pub fn watch(&self, entity_id: &str) -> StateWatch {
+        if let Some(entity) = self.read().get(entity_id) {
+            return entity.watch();
+        }
+        let mut entities = self
+            .entities
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        entities.retain(|_, entity| entity.is_needed());
+        entities
+            .entry(entity_id.to_string())
+            .or_insert_with(Entity::new)
+            .watch()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Entity>> {
+        self.entities
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+async fn follow(
+    mut subscription: Subscription<EntitiesDiff>,
+    entities: Arc<Entities>,
+    mut stopped: oneshot::Receiver<()>,
+) {
+    let mut resubscribed = false;
+
+    loop {
+        let delivery = tokio::select! {
+            delivery = subscription.next_delivery() => match delivery {
+                Some(delivery) => delivery,
+                None => return,
+            },
+            // every clone of the mirror was dropped, dropping the subscription ends it
+            _ = &mut stopped => return,
+        };
+        match delivery {
+            Delivery::Event(Ok(diff)) => {
+                apply(&entities, diff, resubscribed);
+                resubscribed = false;
+            }
+            // a diff that does not decode is skipped, the next change of the entity fixes it
+            Delivery::Event(Err(_)) => {}
+            Delivery::Resubscribed => resubscribed = true,
+        }
+    }
+}
+
+/// Applies `diff`, a `snapshot` holds every entity so the ones missing from it are removed.
+fn apply(entities: &Entities, diff: EntitiesDiff, snapshot: bool) {
+    let mut entities = entities
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    if snapshot {
+        for (entity_id, entity) in entities.iter() {
+            if !diff.added.contains_key(entity_id) {
+                entity.set(None);
+            }
+        }
+    }
+
+    for (entity_id, state) in diff.added {
+        let state = expand(entity_id.clone(), state);
+        entities
+            .entry(entity_id)
+            .or_insert_with(Entity::new)
+            .set(Some(state));
+    }
+
+    for (entity_id, changes) in diff.changed {
+        if let Some(entity) = entities.get(&entity_id) {
+            if let Some(mut state) = entity.state() {
+                patch(&mut state, changes);
+                entity.set(Some(state));
+            }
+        }
+    }
+
+    for entity_id in diff.removed {
+        if let Some(entity) = entities.get(&entity_id) {
+            entity.set(None);
+        }
+    }
+
+    entities.retain(|_, entity| entity.is_needed());
+}
+
+fn expand(entity_id: String, state: CompressedState) -> StateObject {
+    StateObject {
+        entity_id,
+        state: state.state,
+        attributes: state.attributes,
+        last_changed: timestamp(state.last_changed),
+        last_updated: timestamp(state.last_updated.unwrap_or(state.last_changed)),
+        // compressed states do not carry it
+        last_reported: None,
+        context: Some(state.context.into()),
+    }
+}
+
+fn patch(state: &mut StateObject, diff: CompressedStateDiff) {
+    if let Some(additions) = diff.additions {
+        if let Some(new_state) = additions.state {
+            state.state = new_state;
+        }
+        state.attributes.extend(additions.attributes);
+        if let Some(context) = additions.context {
+            state.context = Some(context.into());
+        }
+        // a new last_changed implies the state was updated at the same time
+        if let Some(last_changed) = additions.last_changed {
+            state.last_changed = timestamp(last_changed);
+            state.last_updated = timestamp(last_changed);
+        } else if let Some(last_updated) = additions.last_updated {
+            state.last_updated = timestamp(last_updated);
+        }
+    }
+    if let Some(removals) = diff.removals {
+        for attribute in removals.attributes {
+            state.attributes.remove(&attribute);
+        }
+    }
+}
+
+fn timestamp(seconds: f64) -> DateTime<Utc> {
+    let nanos = (seconds.fract() * 1e9).round() as u32;
+    Utc.timestamp_opt(seconds.trunc() as i64, nanos.min(999_999_999))
+        .single()
+        .unwrap_or_default()
+}
@@ -0,0 +1,229 @@
+use crate::errors;
+use futures::stream::{FuturesUnordered, StreamExt};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+
+const MAX_REQUEST_HEAD: usize = 8192;
+/// How long a connection may take to send its request before it is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long `wait_for_code` waits for the user to log in unless set otherwise.
+const DEFAULT_LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Builds the `/auth/authorize` URL the user has to open in a browser to grant access.
+pub fn authorize_url(
+    instance_url: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    state: &str,
+) -> Result<String, errors::Error> {
+    let url = url::Url::parse_with_params(
+        format!("{}/auth/authorize", instance_url).as_str(),
+        &[
+            ("client_id", client_id),
+            ("redirect_uri", redirect_uri),
+            ("state", state),
+        ],
+    )
+    .map_err(|e| errors::Error::Config(format!("Invalid instance url: {}", e)))?;
+
+    Ok(url.into())
+}
+
+/// Loopback HTTP listener that receives the authorization code once Home Assistant
+/// redirects the browser back to `redirect_uri`.
+#[derive(Debug)]
+pub struct LoginListener {
+    listener: TcpListener,
+    authorize_url: String,
+    redirect_uri: String,
+    state: String,
+    timeout: Duration,
+}
+
+impl LoginListener {
+    /// Binds the listener on `addr`, use port 0 to let the OS pick a free port.
+    ///
+    /// Home Assistant only redirects to a loopback address that shares its host and port
+    /// with `client_id`, unless the client_id page advertises the redirect uri.
+    pub async fn bind(
+        instance_url: &str,
+        client_id: &str,
+        addr: SocketAddr,
+    ) -> Result<Self, errors::Error> {
+        let listener = TcpListener::bind(addr).await?;
+        let redirect_uri = format!("http://{}/", listener.local_addr()?);
+        let state: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .collect();
+        let authorize_url = authorize_url(instance_url, client_id, &redirect_uri, &state)?;
+
+        Ok(Self {
+            listener,
+            authorize_url,
+            redirect_uri,
+            state,
+            timeout: DEFAULT_LOGIN_TIMEOUT,
+        })
+    }
+
+    pub fn authorize_url(&self) -> &str {
+        &self.authorize_url
+    }
+
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    pub fn state(&self) -> &str {
+        &self.state
+    }
+
+    /// How long to wait for the redirect, ten minutes unless set, after which the user
+    /// presumably gave up and the port is released.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Waits for the browser to hit the redirect uri and returns the authorization code.
+    ///
+    /// Connections are served concurrently, so idle sockets a browser opens ahead of time
+    /// do not hold up the redirect, and requests without the callback parameters, such as
+    /// `/favicon.ico`, are answered with a 404 and ignored. Fails with `Error::Timeout`
+    /// when no redirect arrives within the timeout.
+    pub async fn wait_for_code(self) -> Result<String, errors::Error> {
+        let mut listener = self.listener;
+        let state = self.state.as_str();
+        let mut connections = FuturesUnordered::new();
+        let wait = async {
+            loop {
+                tokio::select! {
+                    accepted = listener.accept() => {
+                        let (stream, _) = accepted?;
+                        connections.push(handle_redirect(stream, state));
+                    }
+                    Some(outcome) = connections.next() => {
+                        if let Some(result) = outcome {
+                            return result;
+                        }
+                    }
+                }
+            }
+        };
+        timeout(self.timeout, wait)
+            .await
+            .map_err(|e| errors::Error::Timeout(Box::new(e)))?
+    }
+}
+
+/// Answers one connection to the listener, returns `None` when it was not the redirect.
+async fn handle_redirect(
+    mut stream: TcpStream,
+    expected_state: &str,
+) -> Option<Result<String, errors::Error>> {
+    let target = match timeout(REQUEST_TIMEOUT, read_request_target(&mut stream)).await {
+        Ok(Ok(target)) => target,
+        _ => return None,
+    };
+
+    let url = match url::Url::parse(format!("http://localhost{}", target).as_str()) {
+        Ok(url) if url.path() == "/" => url,
+        _ => {
+            respond(&mut stream, "404 Not Found", "Not Found").await;
+            return None;
+        }
+    };
+
+    let mut code = None;
+    let mut state = None;
+    let mut error = None;
+    for (key, value) in url.query_pairs() {
+        match key.as_ref() {
+            "code" => code = Some(value.into_owned()),
+            "state" => state = Some(value.into_owned()),
+            "error" => error = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+
+    if code.is_none() && error.is_none() {
+        respond(&mut stream, "404 Not Found", "Not Found").await;
+        return None;
+    }
+
+    if state.as_deref() != Some(expected_state) {
+        respond(
+            &mut stream,
+            "400 Bad Request",
+            "Login failed, please try again.",
+        )
+        .await;
+        return Some(Err(errors::Error::StateMismatch));
+    }
+
+    match (code, error) {
+        (_, Some(error)) => {
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "Login failed, please try again.",
+            )
+            .await;
+            Some(Err(errors::Error::HaApi(format!(
+                "Home Assistant refused the authorization request Error: {}",
+                error
+            ))))
+        }
+        (code, None) => {
+            respond(
+                &mut stream,
+                "200 OK",
+                "Login successful, you can close this window.",
+            )
+            .await;
+            code.map(Ok)
+        }
+    }
+}
+
+async fn read_request_target(stream: &mut TcpStream) -> Result<String, errors::Error> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || head.len() + read > MAX_REQUEST_HEAD {
+            return Err(errors::Error::Config(
+                "Malformed login redirect request".to_string(),
+            ));
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some(target)) => Ok(target.to_string()),
+        _ => Err(errors::Error::Config(
+            "Malformed login redirect request".to_string(),
+        )),
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let body = format!("<html><body><p>{}</p></body></html>", message);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    // the browser going away early must not fail the login
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -0,0 +1,342 @@
+use crate::errors;
+use crate::types;
+use crate::websocket::{Subscription, WebSocket};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Client for the entity, device, area, floor and label registries, which live behind the
+/// websocket.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    websocket: WebSocket,
+}
+
+#[derive(Deserialize)]
+struct EntityUpdateResult {
+    entity_entry: types::EntityRegistryEntry,
+}
+
+impl Registry {
+    pub async fn entities(&self) -> Result<Vec<types::EntityRegistryEntry>, errors::Error> {
+        self.websocket
+            .command(json!({ "type": "config/entity_registry/list" }))
+            .await
+    }
+
+    pub async fn get_entity(
+        &self,
+        entity_id: &str,
+    ) -> Result<types::EntityRegistryEntry, errors::Error> {
+        self.websocket
+            .command(json!({
+                "type": "config/entity_registry/get",
+                "entity_id": entity_id,
+            }))
+            .await
+    }
+
+    pub async fn update_entity(
+        &self,
+        entity_id: &str,
+        update: &types::EntityRegistryUpdate,
+    ) -> Result<types::EntityRegistryEntry, errors::Error> {
+        let mut message = json!(update);
+        message["type"] = "config/entity_registry/update".into();
+        message["entity_id"] = entity_id.into();
+        let result: EntityUpdateResult = self.websocket.command(message).await?;
+
+        Ok(result.entity_entry)
+    }
+
+    /// Removes an entity, HA only allows this for entities no integration provides anymore.
+    pub async fn remove_entity(&self, entity_id: &str) -> Result<(), errors::Error> {
+        self.websocket
+            .command::<serde_json::Value>(json!({
+                "type": "config/entity_registry/remove",
+                "entity_id": entity_id,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn devices(&self) -> Result<Vec<types::DeviceRegistryEntry>, errors::Error> {
+        self.websocket
+            .command(json!({ "type": "config/device_registry/list" }))
+            .await
+    }
+
+    /// HA has no command for a single device, so this looks it up in `devices`.
+    pub async fn get_device(
+        &self,
+        device_id: &str,
+    ) -> Result<types::DeviceRegistryEntry, errors::Error> {
+        self.devices()
+            .await?
+            .into_iter()
+            .find(|device| device.id == device_id)
+            .ok_or_else(|| errors::Error::NotFound(format!("Device not found: {}", device_id)))
+    }
+
+    pub async fn update_device(
+        &self,
+        device_id: &str,
+        update: &types::DeviceRegistryUpdate,
+    ) -> Result<types::DeviceRegistryEntry, errors::Error> {
+        let mut message = json!(update);
+        message["type"] = "config/device_registry/update".into();
+        message["device_id"] = device_id.into();
+        self.websocket.command(message).await
+    }
+
+    /// Detaches a device from `config_entry_id`, HA removes the device along with its last
+    /// config entry.
+    pub async fn remove_device(
+        &self,
+        device_id: &str,
+        config_entry_id: &str,
+    ) -> Result<(), errors::Error> {
+        self.websocket
+            .command::<serde_json::Value>(json!({
+                "type": "config/device_registry/remove_config_entry",
+                "device_id": device_id,
+                "config_entry_id": config_entry_id,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn areas(&self) -> Result<Vec<types::AreaRegistryEntry>, errors::Error> {
+        self.websocket
+            .command(json!({ "type": "config/area_registry/list" }))
+            .await
+    }
+
+    /// HA has no command for a single area, so this looks it up in `areas`.
+    pub async fn get_area(&self, area_id: &str) -> Result<types::AreaRegistryEntry, errors::Error> {
+        self.areas()
+            .await?
+            .into_iter()
+            .find(|area| area.area_id == area_id)
+            .ok_or_else(|| errors::Error::NotFound(format!("Area not found: {}", area_id)))
+    }
+
+    pub async fn update_area(
+        &self,
+        area_id: &str,
+        update: &types::AreaRegistryUpdate,
+    ) -> Result<types::AreaRegistryEntry, errors::Error> {
+        let mut message = json!(update);
+        message["type"] = "config/area_registry/update".into();
+        message["area_id"] = area_id.into();
+        self.websocket.command(message).await
+    }
+
+    pub async fn remove_area(&self, area_id: &str) -> Result<(), errors::Error> {
+        self.websocket
+            .command::<serde_json::Value>(json!({
+                "type": "config/area_registry/delete",
+                "area_id": area_id,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn floors(&self) -> Result<Vec<types::FloorRegistryEntry>, errors::Error> {
+        self.websocket
+            .command(json!({ "type": "config/floor_registry/list" }))
+            .await
+    }
+
+    /// Creates a floor named `name`, `fields.name` is ignored.
+    pub async fn create_floor(
+        &self,
+        name: &str,
+        fields: &types::FloorRegistryUpdate,
+    ) -> Result<types::FloorRegistryEntry, errors::Error> {
+        let mut message = json!(fields);
+        message["type"] = "config/floor_registry/create".into();
+        message["name"] = name.into();
+        self.websocket.command(message).await
+    }
+
+    pub async fn update_floor(
+        &self,
+        floor_id: &str,
+        update: &types::FloorRegistryUpdate,
+    ) -> Result<types::FloorRegistryEntry, errors::Error> {
+        let mut message = json!(update);
+        message["type"] = "config/floor_registry/update".into();
+        message["floor_id"] = floor_id.into();
+        self.websocket.command(message).await
+    }
+
+    pub async fn delete_floor(&self, floor_id: &str) -> Result<(), errors::Error> {
+        self.websocket
+            .command::<serde_json::Value>(json!({
+                "type": "config/floor_registry/delete",
+                "floor_id": floor_id,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn labels(&self) -> Result<Vec<types::LabelRegistryEntry>, errors::Error> {
+        self.websocket
+            .command(json!({ "type": "config/label_registry/list" }))
+            .await
+    }
+
+    /// Creates a label named `name`, `fields.name` is ignored.
+    pub async fn create_label(
+        &self,
+        name: &str,
+        fields: &types::LabelRegistryUpdate,
+    ) -> Result<types::LabelRegistryEntry, errors::Error> {
+        let mut message = json!(fields);
+        message["type"] = "config/label_registry/create".into();
+        message["name"] = name.into();
+        self.websocket.command(message).await
+    }
+
+    pub async fn update_label(
+        &self,
+        label_id: &str,
+        update: &types::LabelRegistryUpdate,
+    ) -> Result<types::LabelRegistryEntry, errors::Error> {
+        let mut message = json!(update);
+        message["type"] = "config/label_registry/update".into();
+        message["label_id"] = label_id.into();
+        self.websocket.command(message).await
+    }
+
+    pub async fn delete_label(&self, label_id: &str) -> Result<(), errors::Error> {
+        self.websocket
+            .command::<serde_json::Value>(json!({
+                "type": "config/label_registry/delete",
+                "label_id": label_id,
+            }))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Fetches the entity, device and area registries to resolve entities by area, floor
+    /// or label.
+    pub async fn snapshot(&self) -> Result<RegistrySnapshot, errors::Error> {
+        let (entities, devices, areas) =
+            futures::try_join!(self.entities(), self.devices(), self.areas())?;
+
+        Ok(RegistrySnapshot {
+            entities,
+            devices,
+            areas,
+        })
+    }
+
+    pub async fn subscribe_entity_updates(
+        &self,
+    ) -> Result<Subscription<types::Event<types::EntityRegistryUpdated>>, errors::Error> {
+        self.subscribe_updates("entity_registry_updated").await
+    }
+
+    pub async fn subscribe_device_updates(
+        &self,
+    ) -> Result<Subscription<types::Event<types::DeviceRegistryUpdated>>, errors::Error> {
+        self.subscribe_updates("device_registry_updated").await
+    }
+
+    pub async fn subscribe_area_updates(
+        &self,
+    ) -> Result<Subscription<types::Event<types::AreaRegistryUpdated>>, errors::Error> {
+        self.subscribe_updates("area_registry_updated").await
+    }
+
+    async fn subscribe_updates<T: serde::de::DeserializeOwned>(
+        &self,
+        event_type: &str,
+    ) -> Result<Subscription<types::Event<T>>, errors::Error> {
+        self.websocket
+            .subscribe(json!({
+                "type": "subscribe_events",
+                "event_type": event_type,
+            }))
+            .await
+    }
+}
+
+/// The entity, device and area registries at one point in time.
+///
+/// Entities without an area of their own are in the area of their device, the same way HA
+/// resolves service targets.
+#[derive(Debug, Clone)]
+pub struct RegistrySnapshot {
+    pub entities: Vec<types::EntityRegistryEntry>,
+    pub devices: Vec<types::DeviceRegistryEntry>,
+    pub areas: Vec<types::AreaRegistryEntry>,
+}
+
+impl RegistrySnapshot {
+    /// The area of `entity_id`, directly or through its device.
+    pub fn area_of(&self, entity_id: &str) -> Option<&types::AreaRegistryEntry> {
+        let area_id = self
+            .entities
+            .iter()
+            .find(|entity| entity.entity_id == entity_id)?
+            .area_id(&self.devices)?;
+        self.areas.iter().find(|area| area.area_id == area_id)
+    }
+
+    pub fn entities_in_area<'a>(
+        &'a self,
+        area_id: &'a str,
+    ) -> impl Iterator<Item = &'a types::EntityRegistryEntry> {
+        self.entities
+            .iter()
+            .filter(move |entity| entity.area_id(&self.devices) == Some(area_id))
+    }
+
+    /// The entities in any area of `floor_id`.
+    pub fn entities_on_floor<'a>(
+        &'a self,
+        floor_id: &'a str,
+    ) -> impl Iterator<Item = &'a types::EntityRegistryEntry> {
+        self.entities.iter().filter(move |entity| {
+            entity
+                .area_id(&self.devices)
+                .and_then(|area_id| self.areas.iter().find(|area| area.area_id == area_id))
+                .and_then(|area| area.floor_id.as_deref())
+                == Some(floor_id)
+        })
+    }
+
+    /// The entities labeled `label_id`, or whose device or area is.
+    pub fn entities_with_label<'a>(
+        &'a self,
+        label_id: &'a str,
+    ) -> impl Iterator<Item = &'a types::EntityRegistryEntry> {
+        let labeled = move |labels: &[String]| labels.iter().any(|label| label == label_id);
+        self.entities.iter().filter(move |entity| {
+            labeled(&entity.labels)
+                || entity.device_id.as_ref().is_some_and(|device_id| {
+                    self.devices
+                        .iter()
+                        .any(|device| &device.id == device_id && labeled(&device.labels))
+                })
+                || entity.area_id(&self.devices).is_some_and(|area_id| {
+                    self.areas
+                        .iter()
+                        .any(|area| area.area_id == area_id && labeled(&area.labels))
+                })
+        })
+    }
+}
+
+impl From<WebSocket> for Registry {
+    fn from(websocket: WebSocket) -> Self {
+        Self { websocket }
+    }
+}
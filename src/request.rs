@@ -0,0 +1,115 @@
+use crate::errors;
+use crate::{HomeAssistantAPI, Token};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+impl HomeAssistantAPI {
+    /// Sends an authenticated request to `path` on the instance, `build` adds the query and
+    /// body and is called again when the request is retried.
+    ///
+    /// A 401 refreshes the OAuth token and retries once, any other non 2xx status is
+    /// turned into an error.
+    pub(crate) async fn send<F>(
+        &self,
+        method: Method,
+        path: &str,
+        build: F,
+    ) -> Result<Response, errors::Error>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let endpoint = format!("{}{}", self.instance_url(), path);
+        let mut retried = false;
+
+        loop {
+            let access_token = self.valid_access_token().await?;
+            let request = self
+                .http_client()
+                .request(method.clone(), endpoint.as_str())
+                .bearer_auth(&access_token);
+            let response = build(request).send().await?;
+
+            if response.status() == StatusCode::UNAUTHORIZED && !retried {
+                let refreshable = matches!(*self.inner.token.read().await, Token::Oauth(_));
+                if refreshable {
+                    retried = true;
+                    self.refresh_rejected(&access_token).await?;
+                    continue;
+                }
+            }
+
+            return check_status(response).await;
+        }
+    }
+
+    /// Like `send` but decodes the JSON body of the response.
+    pub(crate) async fn request<T, F>(
+        &self,
+        method: Method,
+        path: &str,
+        build: F,
+    ) -> Result<T, errors::Error>
+    where
+        T: DeserializeOwned,
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let body = self.send(method, path, build).await?.text().await?;
+        decode(body)
+    }
+
+    /// Like `send` but returns the body of the response as text.
+    pub(crate) async fn request_text<F>(
+        &self,
+        method: Method,
+        path: &str,
+        build: F,
+    ) -> Result<String, errors::Error>
+    where
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        Ok(self.send(method, path, build).await?.text().await?)
+    }
+
+    /// Refreshes the token after HA rejected `rejected`, unless another request already did.
+    pub(crate) async fn refresh_rejected(&self, rejected: &str) -> Result<(), errors::Error> {
+        let _refreshing = self.inner.refresh_lock.lock().await;
+        if self.inner.token.read().await.as_string()? == rejected {
+            self.refresh_locked().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Passes 2xx responses through and turns any other status into an error carrying the
+/// message HA sent.
+pub(crate) async fn check_status(response: Response) -> Result<Response, errors::Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await?;
+    let message = match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(error) => error.message,
+        Err(_) => body,
+    };
+    Err(errors::Error::from_status(status, message))
+}
+
+/// Decodes a JSON body, keeping the body in the error when it does not match `T`.
+pub(crate) fn decode<T: DeserializeOwned>(body: String) -> Result<T, errors::Error> {
+    serde_json::from_str(&body).map_err(|e| errors::Error::Decode(e, body))
+}
+
+/// Like `decode` for a body that was already parsed, e.g. a websocket result.
+pub(crate) fn decode_value<T: DeserializeOwned>(
+    value: serde_json::Value,
+) -> Result<T, errors::Error> {
+    T::deserialize(&value).map_err(|e| errors::Error::Decode(e, value.to_string()))
+}
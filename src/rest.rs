@@ -1,601 +1,319 @@
 use crate::errors;
+use crate::history::{self, HistoryQuery};
+use crate::services::ServiceCatalog;
 use crate::types;
+use reqwest::Method;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::convert::TryFrom;
-use std::sync::{Arc, RwLock, Weak};
+use std::collections::HashMap;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Rest {
-    ha_client: Arc<RwLock<crate::HomeAssistantAPI>>,
+    ha_client: crate::HomeAssistantAPI,
 }
 
-impl Rest {
-    pub async fn check(self) -> Result<String, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/config", read_lock.instance_url);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
-
-        #[derive(Serialize, Deserialize, Debug)]
-        struct Response {
-            message: String,
-        }
+#[derive(Serialize, Deserialize, Debug)]
+struct MessageResponse {
+    message: String,
+}
 
-        let resp_json: Response = response.json().await?;
+impl Rest {
+    pub async fn check(&self) -> Result<String, errors::Error> {
+        let resp_json: MessageResponse = self
+            .ha_client
+            .request(Method::GET, "/api/", |request| request)
+            .await?;
 
         Ok(resp_json.message)
-
-        //Err(errors::Error::HaApi(String::from("Not Implemented")))
     }
 
-    pub async fn config(self) -> Result<types::Configuration, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/config", read_lock.instance_url);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
-
-        let resp_json: types::Configuration = response.json().await?;
-
-        Ok(resp_json)
+    pub async fn config(&self) -> Result<types::Configuration, errors::Error> {
+        self.ha_client
+            .request(Method::GET, "/api/config", |request| request)
+            .await
     }
 
-    pub async fn discovery_info(self) -> Result<types::DiscoveryInfo, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/discovery_info", read_lock.instance_url);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
-
-        let resp_json: types::DiscoveryInfo = response.json().await?;
-
-        Ok(resp_json)
+    pub async fn discovery_info(&self) -> Result<types::DiscoveryInfo, errors::Error> {
+        self.ha_client
+            .request(Method::GET, "/api/discovery_info", |request| request)
+            .await
     }
 
-    pub async fn events(self) -> Result<Vec<types::EventObject>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/events", read_lock.instance_url);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
-
-        let resp_json: Vec<types::EventObject> = response.json().await?;
-
-        Ok(resp_json)
+    pub async fn events(&self) -> Result<Vec<types::EventObject>, errors::Error> {
+        self.ha_client
+            .request(Method::GET, "/api/events", |request| request)
+            .await
     }
 
-    pub async fn services(self) -> Result<Vec<types::ServiceObject>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/services", read_lock.instance_url);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
+    pub async fn services(&self) -> Result<Vec<types::ServiceObject>, errors::Error> {
+        self.ha_client
+            .request(Method::GET, "/api/services", |request| request)
+            .await
+    }
 
-        let resp_json: Vec<types::ServiceObject> = response.json().await?;
+    /// The schema of every service, to build forms from or validate service data against.
+    pub async fn service_catalog(&self) -> Result<ServiceCatalog, errors::Error> {
+        Ok(ServiceCatalog::from(self.services().await?))
+    }
 
-        Ok(resp_json)
+    /// Starts a query of the state history of one or more entities.
+    pub fn history(&self) -> HistoryQuery {
+        HistoryQuery::new(self.ha_client.clone())
     }
 
+    /// The states of the comma separated `filter_entity_id` since `timestamp`, flattened
+    /// into one list ordered by entity.
+    #[deprecated(note = "use `Rest::history`, which groups the states by entity")]
     pub async fn history_period(
-        self,
+        &self,
         timestamp: Option<chrono::DateTime<chrono::Utc>>,
         filter_entity_id: Option<String>,
         end_time: Option<chrono::DateTime<chrono::Utc>>,
         significant_changes_only: Option<bool>,
     ) -> Result<Vec<types::StateObject>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let mut endpoint = format!("{}/api/history/period", read_lock.instance_url);
-
-        if let Some(timestamp) = timestamp {
-            let formatted_timestamp = timestamp.format("%Y-%m-%dT%H:%M:%S%:z").to_string();
-            endpoint = endpoint + &formatted_timestamp;
-        }
-
-        let mut request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-
-        if let Some(filter_entity_id) = filter_entity_id {
-            request = request.query(&[("filter_entity_id", filter_entity_id)]);
-        }
-
-        if let Some(end_time) = end_time {
-            let formatted_timestamp = end_time.format("%Y-%m-%dT%H:%M:%S%:z").to_string();
-            request = request.query(&[("end_time", formatted_timestamp)]);
-        }
-
-        if significant_changes_only.is_some() {
-            request = request.query(&[("significant_changes_only")]);
-        }
-
-        let response = request.send().await?;
-
-        let resp_json: Vec<types::StateObject> = response.json().await?;
-
-        Ok(resp_json)
+        let query = history_query(
+            self.history(),
+            timestamp,
+            filter_entity_id,
+            end_time,
+            significant_changes_only,
+        )?;
+        Ok(flatten_history(query.send().await?))
     }
 
+    /// Like `history_period`, with the attributes of every state but the first of each
+    /// entity left empty.
+    #[deprecated(note = "use `Rest::history` with `minimal_response`")]
     pub async fn history_period_minimal(
-        self,
+        &self,
         timestamp: Option<chrono::DateTime<chrono::Utc>>,
         filter_entity_id: Option<String>,
         end_time: Option<chrono::DateTime<chrono::Utc>>,
         significant_changes_only: Option<bool>,
     ) -> Result<Vec<types::StateObject>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let mut endpoint = format!("{}/api/history/period", read_lock.instance_url);
-
-        if let Some(timestamp) = timestamp {
-            let formatted_timestamp = timestamp.format("%Y-%m-%dT%H:%M:%S%:z").to_string();
-            endpoint = endpoint + &formatted_timestamp;
-        }
-
-        let mut request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-
-        if let Some(filter_entity_id) = filter_entity_id {
-            request = request.query(&[("filter_entity_id", filter_entity_id)]);
-        }
-
-        if let Some(end_time) = end_time {
-            let formatted_timestamp = end_time.format("%Y-%m-%dT%H:%M:%S%:z").to_string();
-            request = request.query(&[("end_time", formatted_timestamp)]);
-        }
-
-        if significant_changes_only.is_some() {
-            request = request.query(&[("significant_changes_only")]);
-        }
-
-        request = request.query(&[("minimal_response")]);
-
-        let response = request.send().await?;
-
-        let resp_json: Vec<types::StateObject> = response.json().await?;
-
-        Ok(resp_json)
+        let query = history_query(
+            self.history(),
+            timestamp,
+            filter_entity_id,
+            end_time,
+            significant_changes_only,
+        )?;
+        Ok(flatten_history(query.minimal_response().send().await?))
     }
 
     pub async fn logbook(
-        self,
+        &self,
         timestamp: Option<chrono::DateTime<chrono::Utc>>,
-        entity: String,
+        entity: &types::EntityId,
         end_time: Option<chrono::DateTime<chrono::Utc>>,
     ) -> Result<Vec<types::LogbookEntry>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let mut endpoint = format!("{}/api/logbook", read_lock.instance_url);
+        let mut endpoint = String::from("/api/logbook");
 
         if let Some(timestamp) = timestamp {
-            let formatted_timestamp = timestamp.format("%Y-%m-%dT%H:%M:%S%:z").to_string();
-            endpoint = endpoint + &formatted_timestamp;
-        }
-
-        let mut request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-
-        request = request.query(&[("entity", entity)]);
-
-        if let Some(end_time) = end_time {
-            let formatted_timestamp = end_time.format("%Y-%m-%dT%H:%M:%S%:z").to_string();
-            request = request.query(&[("end_time", formatted_timestamp)]);
+            endpoint = format!("{}/{}", endpoint, history::format_timestamp(timestamp));
         }
 
-        let response = request.send().await?;
+        self.ha_client
+            .request(Method::GET, &endpoint, |mut request| {
+                request = request.query(&[("entity", entity.as_str())]);
 
-        let resp_json: Vec<types::LogbookEntry> = response.json().await?;
+                if let Some(end_time) = end_time {
+                    request = request.query(&[("end_time", history::format_timestamp(end_time))]);
+                }
 
-        Ok(resp_json)
+                request
+            })
+            .await
     }
 
-    pub async fn states(self) -> Result<Vec<types::StateObject>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/states", read_lock.instance_url);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
-
-        let resp_json: Vec<types::StateObject> = response.json().await?;
-
-        Ok(resp_json)
+    pub async fn states(&self) -> Result<Vec<types::StateObject>, errors::Error> {
+        self.ha_client
+            .request(Method::GET, "/api/states", |request| request)
+            .await
     }
 
     pub async fn state_of(
-        self,
-        entity_id: String,
-    ) -> Result<Vec<types::StateObject>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/states/{}", read_lock.instance_url, entity_id);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
-        let resp_json: Vec<types::StateObject> = response.json().await?;
-
-        Ok(resp_json)
+        &self,
+        entity_id: &types::EntityId,
+    ) -> Result<types::StateObject, errors::Error> {
+        let endpoint = format!("/api/states/{}", entity_id.path_segment());
+        self.ha_client
+            .request(Method::GET, &endpoint, |request| request)
+            .await
     }
 
-    pub async fn error_log(self) -> Result<String, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/error_log", read_lock.instance_url);
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let response = request.send().await?;
-
-        let resp: String = response.text().await?;
-
-        Ok(resp)
+    pub async fn error_log(&self) -> Result<String, errors::Error> {
+        self.ha_client
+            .request_text(Method::GET, "/api/error_log", |request| request)
+            .await
     }
 
-    pub async fn camera_proxy(self, camera_entity_id: String) -> Result<(), errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!(
-            "{}/api/camera_proxy/{}",
-            read_lock.instance_url, camera_entity_id
-        );
-        let request = reqwest::Client::new()
-            .get(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-        let _response = request.send().await?;
+    pub async fn camera_proxy(
+        &self,
+        camera_entity_id: &types::EntityId,
+    ) -> Result<(), errors::Error> {
+        let endpoint = format!("/api/camera_proxy/{}", camera_entity_id.path_segment());
+        self.ha_client
+            .send(Method::GET, &endpoint, |request| request)
+            .await?;
 
         Ok(())
     }
 
     pub async fn state_change(
-        self,
-        entity_id: String,
+        &self,
+        entity_id: &types::EntityId,
         state_data: Option<impl serde::Serialize>,
     ) -> Result<types::StateObject, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/states/{}", read_lock.instance_url, entity_id);
-        let mut request = reqwest::Client::new()
-            .post(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-
-        if let Some(data) = state_data {
-            request = request.json(&data);
-        }
-
-        let response = request.send().await?;
-
-        let resp_json: types::StateObject = response.json().await?;
-
-        Ok(resp_json)
+        let endpoint = format!("/api/states/{}", entity_id.path_segment());
+        self.ha_client
+            .request(Method::POST, &endpoint, |request| match &state_data {
+                Some(data) => request.json(data),
+                None => request,
+            })
+            .await
     }
 
     pub async fn event_fire(
-        self,
+        &self,
         event_type: String,
         event_data: Option<impl serde::Serialize>,
     ) -> Result<String, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/events/{}", read_lock.instance_url, event_type);
-        let mut request = reqwest::Client::new()
-            .post(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-
-        if let Some(data) = event_data {
-            request = request.json(&data);
-        }
-
-        let response = request.send().await?;
-
-        #[derive(Serialize, Deserialize, Debug)]
-        struct Response {
-            message: String,
-        }
-
-        let resp_json: Response = response.json().await?;
+        let endpoint = format!("/api/events/{}", event_type);
+        let resp_json: MessageResponse = self
+            .ha_client
+            .request(Method::POST, &endpoint, |request| match &event_data {
+                Some(data) => request.json(data),
+                None => request,
+            })
+            .await?;
 
         Ok(resp_json.message)
     }
 
-    pub async fn service_call<T>(
-        self,
+    pub async fn service_call(
+        &self,
         domain: String,
         service: String,
         service_data: Option<impl serde::Serialize>,
     ) -> Result<Vec<types::StateObject>, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!(
-            "{}/api/services/{}/{}",
-            read_lock.instance_url, domain, service
-        );
-        let mut request = reqwest::Client::new()
-            .post(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-
-        if let Some(data) = service_data {
-            request = request.json(&data);
-        }
-
-        let response = request.send().await?;
-
-        let resp_json: Vec<types::StateObject> = response.json().await?;
-
-        Ok(resp_json)
+        let endpoint = format!("/api/services/{}/{}", domain, service);
+        self.ha_client
+            .request(Method::POST, &endpoint, |request| match &service_data {
+                Some(data) => request.json(data),
+                None => request,
+            })
+            .await
     }
 
-    pub async fn template_render(self, template: String) -> Result<String, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
+    /// Validates `service_data` against `catalog` and only calls the service if it matches.
+    pub async fn validated_service_call(
+        &self,
+        catalog: &ServiceCatalog,
+        domain: String,
+        service: String,
+        service_data: Option<impl serde::Serialize>,
+    ) -> Result<Vec<types::StateObject>, errors::Error> {
+        let service_data = match service_data {
+            Some(data) => serde_json::to_value(data)?,
+            None => serde_json::Value::Null,
+        };
+        catalog.validate(&domain, &service, &service_data)?;
+
+        self.service_call(
+            domain,
+            service,
+            Some(service_data).filter(|data| !data.is_null()),
+        )
+        .await
+    }
 
-        let endpoint = format!("{}/api/template", read_lock.instance_url);
+    /// Calls a service that returns data, such as `weather.get_forecasts`, and decodes
+    /// its response into `T`.
+    pub async fn service_call_with_response<T: DeserializeOwned>(
+        &self,
+        domain: String,
+        service: String,
+        service_data: Option<impl serde::Serialize>,
+    ) -> Result<types::ServiceResponse<T>, errors::Error> {
+        let endpoint = format!("/api/services/{}/{}", domain, service);
+        self.ha_client
+            .request(Method::POST, &endpoint, |request| {
+                let request = request.query(&[("return_response", "")]);
+                match &service_data {
+                    Some(data) => request.json(data),
+                    None => request,
+                }
+            })
+            .await
+    }
 
+    pub async fn template_render(&self, template: String) -> Result<String, errors::Error> {
         #[derive(Serialize, Deserialize, Debug)]
         struct Template {
             template: String,
         }
 
         let template_struct = Template { template };
-        let request = reqwest::Client::new()
-            .post(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json");
-
-        drop(read_lock);
-
-        let response = request.json(&template_struct).send().await?;
-
-        let resp: String = response.text().await?;
-
-        Ok(resp)
+        self.ha_client
+            .request_text(Method::POST, "/api/template", |request| {
+                request.json(&template_struct)
+            })
+            .await
     }
 
-    pub async fn check_config(self) -> Result<types::CheckConfig, errors::Error> {
-        let mut read_lock = self.ha_client.read().unwrap();
-        if read_lock.token.need_refresh() {
-            drop(read_lock);
-            let mut write_lock = self.ha_client.write().unwrap();
-            write_lock.refresh_oauth_token().await?;
-            read_lock = self.ha_client.read().unwrap();
-        }
-
-        let endpoint = format!("{}/api/config/core/check_config", read_lock.instance_url);
-        let response = reqwest::Client::new()
-            .post(endpoint.as_str())
-            .header(
-                "Authorization",
-                format!("Bearer {}", read_lock.token.as_string()?),
-            )
-            .header("content-type", "application/json")
-            .send()
-            .await?;
-
-        drop(read_lock);
-
-        let resp_json: types::CheckConfig = response.json().await?;
-
-        Ok(resp_json)
+    pub async fn check_config(&self) -> Result<types::CheckConfig, errors::Error> {
+        self.ha_client
+            .request(Method::POST, "/api/config/core/check_config", |request| {
+                request
+            })
+            .await
     }
 }
 
-impl TryFrom<Weak<RwLock<crate::HomeAssistantAPI>>> for Rest {
-    type Error = errors::Error;
-
-    fn try_from(weak: Weak<RwLock<crate::HomeAssistantAPI>>) -> Result<Self, Self::Error> {
-        match weak.upgrade() {
-            Some(ptr) => Ok(Self { ha_client: ptr }),
-            None => Err(errors::Error::HaApi(String::from(
-                "Can't create Rest Client weak ptr returned none",
-            ))),
-        }
+impl From<crate::HomeAssistantAPI> for Rest {
+    fn from(ha_client: crate::HomeAssistantAPI) -> Self {
+        Self { ha_client }
     }
 }
 
-impl From<Arc<RwLock<crate::HomeAssistantAPI>>> for Rest {
-    fn from(ptr: Arc<RwLock<crate::HomeAssistantAPI>>) -> Self {
-        Self { ha_client: ptr }
+fn history_query(
+    mut query: HistoryQuery,
+    timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    filter_entity_id: Option<String>,
+    end_time: Option<chrono::DateTime<chrono::Utc>>,
+    significant_changes_only: Option<bool>,
+) -> Result<HistoryQuery, errors::Error> {
+    for entity_id in filter_entity_id.iter().flat_map(|ids| ids.split(',')) {
+        query = query.entity_id(entity_id.trim().parse()?);
+    }
+    if let Some(timestamp) = timestamp {
+        query = query.start(timestamp);
     }
+    if let Some(end_time) = end_time {
+        query = query.end(end_time);
+    }
+    if let Some(significant_changes_only) = significant_changes_only {
+        query = query.significant_changes_only(significant_changes_only);
+    }
+    Ok(query)
+}
+
+fn flatten_history(history: HashMap<String, Vec<types::HistoryState>>) -> Vec<types::StateObject> {
+    let mut history: Vec<_> = history.into_iter().collect();
+    history.sort_by(|(a, _), (b, _)| a.cmp(b));
+    history
+        .into_iter()
+        .flat_map(|(entity_id, states)| {
+            states.into_iter().map(move |state| types::StateObject {
+                attributes: state.attributes.unwrap_or_default(),
+                entity_id: entity_id.clone(),
+                last_changed: state.last_changed,
+                last_updated: state.last_updated,
+                last_reported: None,
+                state: state.state,
+                context: None,
+            })
+        })
+        .collect()
 }
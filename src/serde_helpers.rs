@@ -0,0 +1,22 @@
+use serde::{Deserialize, Deserializer, Serializer};
+use std::time::Duration;
+
+/// Serializes a duration as the fractional seconds HA expects, e.g. `1.5`.
+pub(crate) fn serialize_seconds<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_f64(duration.as_secs_f64()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Deserializes `null` like a missing value, for fields HA sometimes sends as `null`.
+pub(crate) fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
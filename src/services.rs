@@ -0,0 +1,187 @@
+use crate::errors;
+use crate::types::{self, Selector, ServiceDescription};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Keys HA accepts in the service data of services that take a target.
+const TARGET_KEYS: [&str; 5] = ["entity_id", "device_id", "area_id", "floor_id", "label_id"];
+
+/// Every service HA offers, by domain and service name, as returned by `Rest::services`.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceCatalog {
+    domains: HashMap<String, HashMap<String, ServiceDescription>>,
+}
+
+impl ServiceCatalog {
+    pub fn get(&self, domain: &str, service: &str) -> Option<&ServiceDescription> {
+        self.domains.get(domain)?.get(service)
+    }
+
+    pub fn domains(&self) -> impl Iterator<Item = &str> {
+        self.domains.keys().map(String::as_str)
+    }
+
+    /// The services of `domain` with their descriptions.
+    pub fn services(&self, domain: &str) -> Option<&HashMap<String, ServiceDescription>> {
+        self.domains.get(domain)
+    }
+
+    /// Checks `service_data` against the schema of `domain.service`, the same data that would
+    /// be sent to `service_call`.
+    pub fn validate(
+        &self,
+        domain: &str,
+        service: &str,
+        service_data: &Value,
+    ) -> Result<(), errors::Error> {
+        let description = self.get(domain, service).ok_or_else(|| {
+            errors::Error::InvalidServiceData(format!("Unknown service {}.{}", domain, service))
+        })?;
+        validate(description, service_data)
+    }
+}
+
+impl From<Vec<types::ServiceObject>> for ServiceCatalog {
+    fn from(services: Vec<types::ServiceObject>) -> Self {
+        Self {
+            domains: services
+                .into_iter()
+                .map(|object| (object.domain, object.services))
+                .collect(),
+        }
+    }
+}
+
+/// Checks `service_data` against `description`.
+///
+/// Services HA knows no fields of, such as scripts without a description, accept any data.
+pub fn validate(
+    description: &ServiceDescription,
+    service_data: &Value,
+) -> Result<(), errors::Error> {
+    let empty = serde_json::Map::new();
+    let data = match service_data {
+        Value::Null => &empty,
+        Value::Object(data) => data,
+        _ => return Err(invalid("Service data must be an object")),
+    };
+    let fields: HashMap<&str, &types::ServiceField> =
+        description.all_fields().into_iter().collect();
+
+    for (name, field) in &fields {
+        if field.required && !data.contains_key(*name) {
+            return Err(invalid(format!("Missing required field {}", name)));
+        }
+    }
+
+    for (name, value) in data {
+        if let Some(field) = fields.get(name.as_str()) {
+            if let Some(selector) = &field.selector {
+                check_selector(name, selector, value)?;
+            }
+        } else if TARGET_KEYS.contains(&name.as_str()) && description.target.is_some() {
+            check_ids(name, value, true)?;
+        } else if !fields.is_empty() {
+            return Err(invalid(format!("Unknown field {}", name)));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_selector(name: &str, selector: &Selector, value: &Value) -> Result<(), errors::Error> {
+    match selector {
+        Selector::Boolean if !value.is_boolean() => {
+            Err(invalid(format!("Field {} must be a boolean", name)))
+        }
+        Selector::Number(number) => {
+            let number_value = value
+                .as_f64()
+                .ok_or_else(|| invalid(format!("Field {} must be a number", name)))?;
+            if number.min.is_some_and(|min| number_value < min)
+                || number.max.is_some_and(|max| number_value > max)
+            {
+                return Err(invalid(format!(
+                    "Field {} must be between {} and {}",
+                    name,
+                    number.min.map_or("-inf".to_string(), |min| min.to_string()),
+                    number.max.map_or("inf".to_string(), |max| max.to_string()),
+                )));
+            }
+            Ok(())
+        }
+        Selector::Text(text) => {
+            for value in values(name, value, text.multiple)? {
+                if !value.is_string() {
+                    return Err(invalid(format!("Field {} must be text", name)));
+                }
+            }
+            Ok(())
+        }
+        Selector::Select(select) => {
+            for value in values(name, value, select.multiple)? {
+                let value = value
+                    .as_str()
+                    .ok_or_else(|| invalid(format!("Field {} must be one of its options", name)))?;
+                if !select.custom_value
+                    && !select.options.iter().any(|option| option.value() == value)
+                {
+                    return Err(invalid(format!(
+                        "Field {} does not allow the option {}",
+                        name, value
+                    )));
+                }
+            }
+            Ok(())
+        }
+        Selector::Entity(entity) => {
+            check_ids(name, value, entity.multiple)?;
+            let domains: Vec<&str> = entity
+                .filter
+                .iter()
+                .flat_map(|filter| filter.domain.iter().map(String::as_str))
+                .collect();
+            if domains.is_empty() {
+                return Ok(());
+            }
+            for entity_id in values(name, value, entity.multiple)? {
+                let entity_id = entity_id.as_str().unwrap_or_default();
+                let domain = entity_id.split('.').next().unwrap_or_default();
+                if !domains.contains(&domain) {
+                    return Err(invalid(format!(
+                        "Field {} does not allow the entity {}",
+                        name, entity_id
+                    )));
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks a value that holds one id, or a list of them when `multiple` is allowed.
+fn check_ids(name: &str, value: &Value, multiple: bool) -> Result<(), errors::Error> {
+    for id in values(name, value, multiple)? {
+        if !id.is_string() {
+            return Err(invalid(format!("Field {} must hold ids", name)));
+        }
+    }
+    Ok(())
+}
+
+fn values<'a>(
+    name: &str,
+    value: &'a Value,
+    multiple: bool,
+) -> Result<Vec<&'a Value>, errors::Error> {
+    match value {
+        Value::Array(values) if multiple => Ok(values.iter().collect()),
+        Value::Array(_) => Err(invalid(format!("Field {} takes a single value", name))),
+        value => Ok(vec![value]),
+    }
+}
+
+fn invalid(message: impl Into<String>) -> errors::Error {
+    errors::Error::InvalidServiceData(message.into())
+}
@@ -0,0 +1,114 @@
+use crate::errors;
+use crate::types::{StatisticType, StatisticsPeriod, StatisticsRow};
+use crate::websocket::WebSocket;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Builder for `recorder/statistics_during_period`, created by
+/// `WebSocket::statistics_during_period`.
+///
+/// ```no_run
+/// # use homeassistant::types::{StatisticType, StatisticsPeriod};
+/// # async fn example(websocket: homeassistant::websocket::WebSocket) -> Result<(), homeassistant::errors::Error> {
+/// let statistics = websocket
+///     .statistics_during_period(chrono::Utc::now() - chrono::Duration::days(7))
+///     .statistic_id("sensor.energy_meter")
+///     .period(StatisticsPeriod::Day)
+///     .types(vec![StatisticType::Change])
+///     .unit("energy", "kWh")
+///     .send()
+///     .await?;
+/// for day in &statistics["sensor.energy_meter"] {
+///     println!("{} {:?} kWh", day.start, day.change);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct StatisticsQuery {
+    websocket: WebSocket,
+    message: StatisticsMessage,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct StatisticsMessage {
+    #[serde(rename = "type")]
+    message_type: &'static str,
+    start_time: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_time: Option<DateTime<Utc>>,
+    statistic_ids: Vec<String>,
+    period: StatisticsPeriod,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    units: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    types: Option<Vec<StatisticType>>,
+}
+
+impl StatisticsQuery {
+    pub(crate) fn new(websocket: WebSocket, start: DateTime<Utc>) -> Self {
+        Self {
+            websocket,
+            message: StatisticsMessage {
+                message_type: "recorder/statistics_during_period",
+                start_time: start,
+                end_time: None,
+                statistic_ids: Vec::new(),
+                period: StatisticsPeriod::Hour,
+                units: HashMap::new(),
+                types: None,
+            },
+        }
+    }
+
+    /// Adds a statistic to query, an entity id or an external id such as
+    /// `tibber:energy_consumption`.
+    pub fn statistic_id(mut self, statistic_id: impl Into<String>) -> Self {
+        self.message.statistic_ids.push(statistic_id.into());
+        self
+    }
+
+    pub fn statistic_ids<I, S>(mut self, statistic_ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.message
+            .statistic_ids
+            .extend(statistic_ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// End of the period, HA defaults to now.
+    pub fn end(mut self, end: DateTime<Utc>) -> Self {
+        self.message.end_time = Some(end);
+        self
+    }
+
+    /// The length of each row, an hour unless set.
+    pub fn period(mut self, period: StatisticsPeriod) -> Self {
+        self.message.period = period;
+        self
+    }
+
+    /// Converts the statistics of `unit_class`, such as `energy`, to `unit`, such as `kWh`.
+    pub fn unit(mut self, unit_class: impl Into<String>, unit: impl Into<String>) -> Self {
+        self.message.units.insert(unit_class.into(), unit.into());
+        self
+    }
+
+    /// Only returns these columns, HA returns all of them unless set.
+    pub fn types(mut self, types: Vec<StatisticType>) -> Self {
+        self.message.types = Some(types);
+        self
+    }
+
+    /// The rows of every queried statistic, oldest first. Statistics without rows in the
+    /// period are left out.
+    pub async fn send(self) -> Result<HashMap<String, Vec<StatisticsRow>>, errors::Error> {
+        self.websocket
+            .command(serde_json::json!(self.message))
+            .await
+    }
+}
@@ -0,0 +1,90 @@
+use crate::errors;
+use crate::Token;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Storage `HomeAssistantAPI` writes its token to whenever it is set or refreshed.
+pub trait TokenStore: std::fmt::Debug + Send + Sync {
+    /// Returns the saved token, `None` when nothing was saved yet.
+    fn load(&self) -> Result<Option<Token>, errors::Error>;
+
+    fn save(&self, token: &Token) -> Result<(), errors::Error>;
+}
+
+/// Keeps the token in memory only, useful for tests and to share a session between clients.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    token: Mutex<Option<Token>>,
+}
+
+impl MemoryTokenStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self) -> Result<Option<Token>, errors::Error> {
+        let token = self.token.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(token.clone())
+    }
+
+    fn save(&self, token: &Token) -> Result<(), errors::Error> {
+        let mut stored = self.token.lock().unwrap_or_else(|e| e.into_inner());
+        *stored = Some(token.clone());
+        Ok(())
+    }
+}
+
+/// Saves the token as JSON in a file only readable by the current user.
+#[derive(Debug)]
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> Result<Option<Token>, errors::Error> {
+        match fs::read(&self.path) {
+            Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, token: &Token) -> Result<(), errors::Error> {
+        let contents = serde_json::to_vec(token)?;
+
+        // write next to the target and rename so a crash never leaves a truncated token
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&tmp_path)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
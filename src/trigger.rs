@@ -0,0 +1,363 @@
+use serde::Serialize;
+use std::time::Duration;
+
+/// A trigger for `WebSocket::subscribe_trigger`, serialized to the same schema as the
+/// triggers of HA automations.
+///
+/// ```no_run
+/// # use homeassistant::trigger::Trigger;
+/// # use std::time::Duration;
+/// let trigger = Trigger::state(vec!["binary_sensor.front_door"])
+///     .to("on")
+///     .for_duration(Duration::from_secs(30));
+/// ```
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "platform", rename_all = "snake_case")]
+pub enum Trigger {
+    State(StateTrigger),
+    NumericState(NumericStateTrigger),
+    TimePattern(TimePatternTrigger),
+    Time(TimeTrigger),
+    Template(TemplateTrigger),
+    Event(EventTrigger),
+    #[serde(rename = "homeassistant")]
+    HomeAssistant(HomeAssistantTrigger),
+}
+
+impl Trigger {
+    /// Fires when the state of any of `entity_ids` changes.
+    pub fn state<I, S>(entity_ids: I) -> StateTrigger
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        StateTrigger {
+            entity_id: entity_ids.into_iter().map(Into::into).collect(),
+            attribute: None,
+            from: None,
+            to: None,
+            duration: None,
+            id: None,
+        }
+    }
+
+    /// Fires when a numeric state of any of `entity_ids` crosses `above` or `below`.
+    pub fn numeric_state<I, S>(entity_ids: I) -> NumericStateTrigger
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        NumericStateTrigger {
+            entity_id: entity_ids.into_iter().map(Into::into).collect(),
+            attribute: None,
+            above: None,
+            below: None,
+            value_template: None,
+            duration: None,
+            id: None,
+        }
+    }
+
+    /// Fires when the time matches a pattern such as every 5 minutes (`minutes("/5")`).
+    pub fn time_pattern() -> TimePatternTrigger {
+        TimePatternTrigger {
+            hours: None,
+            minutes: None,
+            seconds: None,
+            id: None,
+        }
+    }
+
+    /// Fires daily at `at`, a time such as `"07:30:00"` or an `input_datetime` entity.
+    pub fn time(at: impl Into<String>) -> TimeTrigger {
+        TimeTrigger {
+            at: at.into(),
+            id: None,
+        }
+    }
+
+    /// Fires when `value_template` starts rendering to true.
+    pub fn template(value_template: impl Into<String>) -> TemplateTrigger {
+        TemplateTrigger {
+            value_template: value_template.into(),
+            duration: None,
+            id: None,
+        }
+    }
+
+    /// Fires on every event of `event_type`.
+    pub fn event(event_type: impl Into<String>) -> EventTrigger {
+        EventTrigger {
+            event_type: event_type.into(),
+            event_data: None,
+            id: None,
+        }
+    }
+
+    /// Fires when HA starts.
+    pub fn home_assistant_start() -> HomeAssistantTrigger {
+        HomeAssistantTrigger {
+            event: HomeAssistantEvent::Start,
+            id: None,
+        }
+    }
+
+    /// Fires when HA shuts down.
+    pub fn home_assistant_shutdown() -> HomeAssistantTrigger {
+        HomeAssistantTrigger {
+            event: HomeAssistantEvent::Shutdown,
+            id: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StateTrigger {
+    entity_id: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attribute: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to: Option<String>,
+    #[serde(
+        rename = "for",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serde_helpers::serialize_seconds"
+    )]
+    duration: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl StateTrigger {
+    /// Watches an attribute instead of the state.
+    pub fn attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.attribute = Some(attribute.into());
+        self
+    }
+
+    pub fn from(mut self, state: impl Into<String>) -> Self {
+        self.from = Some(state.into());
+        self
+    }
+
+    pub fn to(mut self, state: impl Into<String>) -> Self {
+        self.to = Some(state.into());
+        self
+    }
+
+    /// Only fires once the new state was kept for `duration`.
+    pub fn for_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Id reported as `trigger.id` when this trigger fires.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct NumericStateTrigger {
+    entity_id: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attribute: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    above: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    below: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_template: Option<String>,
+    #[serde(
+        rename = "for",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serde_helpers::serialize_seconds"
+    )]
+    duration: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl NumericStateTrigger {
+    /// Watches an attribute instead of the state.
+    pub fn attribute(mut self, attribute: impl Into<String>) -> Self {
+        self.attribute = Some(attribute.into());
+        self
+    }
+
+    pub fn above(mut self, value: f64) -> Self {
+        self.above = Some(value);
+        self
+    }
+
+    pub fn below(mut self, value: f64) -> Self {
+        self.below = Some(value);
+        self
+    }
+
+    /// Template applied to the state before comparing, the state is available as `state`.
+    pub fn value_template(mut self, template: impl Into<String>) -> Self {
+        self.value_template = Some(template.into());
+        self
+    }
+
+    /// Only fires once the value stayed in range for `duration`.
+    pub fn for_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Id reported as `trigger.id` when this trigger fires.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TimePatternTrigger {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hours: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minutes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seconds: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl TimePatternTrigger {
+    /// A value such as `"7"`, `"*"` or `"/2"` for every second hour.
+    pub fn hours(mut self, pattern: impl Into<String>) -> Self {
+        self.hours = Some(pattern.into());
+        self
+    }
+
+    pub fn minutes(mut self, pattern: impl Into<String>) -> Self {
+        self.minutes = Some(pattern.into());
+        self
+    }
+
+    pub fn seconds(mut self, pattern: impl Into<String>) -> Self {
+        self.seconds = Some(pattern.into());
+        self
+    }
+
+    /// Id reported as `trigger.id` when this trigger fires.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TimeTrigger {
+    at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl TimeTrigger {
+    /// Id reported as `trigger.id` when this trigger fires.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TemplateTrigger {
+    value_template: String,
+    #[serde(
+        rename = "for",
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::serde_helpers::serialize_seconds"
+    )]
+    duration: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl TemplateTrigger {
+    /// Only fires once the template stayed true for `duration`.
+    pub fn for_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Id reported as `trigger.id` when this trigger fires.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EventTrigger {
+    event_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    event_data: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl EventTrigger {
+    /// Only fires for events whose data contains `data`.
+    pub fn event_data(mut self, data: serde_json::Value) -> Self {
+        self.event_data = Some(data);
+        self
+    }
+
+    /// Id reported as `trigger.id` when this trigger fires.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct HomeAssistantTrigger {
+    event: HomeAssistantEvent,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+}
+
+impl HomeAssistantTrigger {
+    /// Id reported as `trigger.id` when this trigger fires.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum HomeAssistantEvent {
+    Start,
+    Shutdown,
+}
+
+macro_rules! impl_into_trigger {
+    ($($platform:ident => $variant:ident),*) => {
+        $(
+            impl From<$platform> for Trigger {
+                fn from(trigger: $platform) -> Self {
+                    Trigger::$variant(trigger)
+                }
+            }
+        )*
+    };
+}
+
+impl_into_trigger!(
+    StateTrigger => State,
+    NumericStateTrigger => NumericState,
+    TimePatternTrigger => TimePattern,
+    TimeTrigger => Time,
+    TemplateTrigger => Template,
+    EventTrigger => Event,
+    HomeAssistantTrigger => HomeAssistant
+);
@@ -46,6 +46,13 @@ pub struct GetAccessTokenResponse {
     pub token_type: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshAccessTokenRequest {
+    pub grant_type: String,
+    pub refresh_token: String,
+    pub client_id: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RefreshAccessTokenResponse {
     pub access_token: String,
@@ -56,9 +63,87 @@ pub struct RefreshAccessTokenResponse {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GetAccessTokenError {
     pub error: String,
+    #[serde(default)]
     pub error_description: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RevokeTokenRequest {
+    pub token: String,
+    pub action: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshTokenInfo {
+    pub id: String,
+    pub client_id: Option<String>,
+    pub client_name: Option<String>,
+    pub client_icon: Option<String>,
+    pub r#type: String,
+    pub created_at: String,
+    pub is_current: bool,
+    pub last_used_at: Option<String>,
+    pub last_used_ip: Option<String>,
+    pub auth_provider_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginFlowRequest {
+    pub client_id: String,
+    pub handler: (String, Option<String>),
+    pub redirect_uri: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LoginFlowStep {
+    Form(LoginFlowForm),
+    CreateEntry(LoginFlowResult),
+    Abort(LoginFlowAbort),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginFlowForm {
+    pub flow_id: String,
+    pub step_id: LoginFlowStepId,
+    #[serde(default)]
+    pub data_schema: Vec<LoginFlowField>,
+    #[serde(default, deserialize_with = "crate::serde_helpers::null_as_default")]
+    pub errors: std::collections::HashMap<String, String>,
+    pub description_placeholders: Option<std::collections::HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginFlowStepId {
+    Init,
+    SelectMfaModule,
+    Mfa,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginFlowField {
+    pub name: String,
+    pub r#type: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    pub options: Option<Vec<(String, String)>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginFlowResult {
+    pub flow_id: String,
+    pub result: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginFlowAbort {
+    pub flow_id: String,
+    pub reason: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SensorRegistrationRequest {
     pub r#type: String,
@@ -133,19 +218,684 @@ pub struct EventObject {
     pub listener_count: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// An event fired on the HA event bus, as delivered by `subscribe_events`.
+///
+/// `data` stays untyped unless the subscription knows the shape of its events.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Event<T = serde_json::Value> {
+    pub event_type: String,
+    pub data: T,
+    pub origin: String,
+    pub time_fired: chrono::DateTime<chrono::Utc>,
+    pub context: Context,
+}
+
+/// Identifies what caused a state change or event and which user triggered it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Context {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub user_id: Option<String>,
+}
+
+/// Answer of a service call made with `return_response`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceResponse<T> {
+    pub changed_states: Vec<StateObject>,
+    pub service_response: T,
+}
+
+/// The entities a service call acts on, given directly or through their device, area,
+/// floor or label.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ServiceTarget {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entity_id: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub device_id: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub area_id: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub floor_id: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub label_id: Vec<String>,
+}
+
+impl ServiceTarget {
+    pub fn entities<I: IntoIterator<Item = S>, S: Into<String>>(entity_ids: I) -> Self {
+        Self {
+            entity_id: entity_ids.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn devices<I: IntoIterator<Item = S>, S: Into<String>>(device_ids: I) -> Self {
+        Self {
+            device_id: device_ids.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn areas<I: IntoIterator<Item = S>, S: Into<String>>(area_ids: I) -> Self {
+        Self {
+            area_id: area_ids.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn floors<I: IntoIterator<Item = S>, S: Into<String>>(floor_ids: I) -> Self {
+        Self {
+            floor_id: floor_ids.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn labels<I: IntoIterator<Item = S>, S: Into<String>>(label_ids: I) -> Self {
+        Self {
+            label_id: label_ids.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Result of `call_service` over the websocket.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServiceCallResult<T> {
+    pub context: Context,
+    pub response: T,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ServiceObject {
     pub domain: String,
-    pub services: Vec<String>,
+    pub services: std::collections::HashMap<String, ServiceDescription>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// How a service is described in `/api/services`, the schema HA renders its forms from.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServiceDescription {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub fields: std::collections::HashMap<String, ServiceField>,
+    /// Present when the service accepts a target, limited to the matching entities and devices.
+    #[serde(default)]
+    pub target: Option<TargetSelector>,
+    /// Present when the service can return data.
+    #[serde(default)]
+    pub response: Option<ServiceResponseDescription>,
+}
+
+impl ServiceDescription {
+    /// Every field, including the ones nested in collapsible sections.
+    pub fn all_fields(&self) -> Vec<(&str, &ServiceField)> {
+        fn collect<'a>(
+            fields: &'a std::collections::HashMap<String, ServiceField>,
+            into: &mut Vec<(&'a str, &'a ServiceField)>,
+        ) {
+            for (name, field) in fields {
+                if field.is_section() {
+                    collect(&field.fields, into);
+                } else {
+                    into.push((name, field));
+                }
+            }
+        }
+
+        let mut fields = Vec::new();
+        collect(&self.fields, &mut fields);
+        fields
+    }
+}
+
+/// A field of a service, or a collapsible section grouping more `fields`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServiceField {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub advanced: bool,
+    #[serde(default)]
+    pub example: Option<serde_json::Value>,
+    #[serde(default)]
+    pub default: Option<serde_json::Value>,
+    #[serde(default)]
+    pub selector: Option<Selector>,
+    /// Only shows the field for targets matching this filter.
+    #[serde(default)]
+    pub filter: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "std::collections::HashMap::is_empty")]
+    pub fields: std::collections::HashMap<String, ServiceField>,
+    #[serde(default)]
+    pub collapsed: bool,
+}
+
+impl ServiceField {
+    pub fn is_section(&self) -> bool {
+        !self.fields.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ServiceResponseDescription {
+    /// Whether the caller may leave out `return_response`.
+    #[serde(default)]
+    pub optional: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TargetSelector {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub entity: Vec<EntityFilter>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub device: Vec<DeviceFilter>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EntityFilter {
+    #[serde(default)]
+    pub integration: Option<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub domain: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub device_class: Vec<String>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub supported_features: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DeviceFilter {
+    #[serde(default)]
+    pub integration: Option<String>,
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// The input a field takes, serialized as `{"<kind>": {<config>}}` like HA selectors.
+///
+/// Selectors without a dedicated variant keep their kind and config in `Other`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(
+    try_from = "std::collections::HashMap<String, serde_json::Value>",
+    into = "std::collections::HashMap<String, serde_json::Value>"
+)]
+pub enum Selector {
+    Boolean,
+    Number(NumberSelector),
+    Text(TextSelector),
+    Select(SelectSelector),
+    Entity(EntitySelector),
+    Object,
+    Other(String, serde_json::Value),
+}
+
+impl std::convert::TryFrom<std::collections::HashMap<String, serde_json::Value>> for Selector {
+    type Error = String;
+
+    fn try_from(
+        selector: std::collections::HashMap<String, serde_json::Value>,
+    ) -> Result<Self, Self::Error> {
+        fn config_of<T: serde::de::DeserializeOwned>(
+            config: serde_json::Value,
+        ) -> Result<T, String> {
+            serde_json::from_value(config).map_err(|e| e.to_string())
+        }
+
+        let (kind, config) = selector
+            .into_iter()
+            .next()
+            .ok_or_else(|| String::from("empty selector"))?;
+        // HA leaves out the config of selectors without options
+        let config = match config {
+            serde_json::Value::Null => serde_json::json!({}),
+            config => config,
+        };
+
+        Ok(match kind.as_str() {
+            "boolean" => Selector::Boolean,
+            "number" => Selector::Number(config_of(config)?),
+            "text" => Selector::Text(config_of(config)?),
+            "select" => Selector::Select(config_of(config)?),
+            "entity" => Selector::Entity(config_of(config)?),
+            "object" => Selector::Object,
+            _ => Selector::Other(kind, config),
+        })
+    }
+}
+
+impl From<Selector> for std::collections::HashMap<String, serde_json::Value> {
+    fn from(selector: Selector) -> Self {
+        let (kind, config) = match selector {
+            Selector::Boolean => ("boolean".to_string(), serde_json::json!({})),
+            Selector::Number(config) => ("number".to_string(), serde_json::json!(config)),
+            Selector::Text(config) => ("text".to_string(), serde_json::json!(config)),
+            Selector::Select(config) => ("select".to_string(), serde_json::json!(config)),
+            Selector::Entity(config) => ("entity".to_string(), serde_json::json!(config)),
+            Selector::Object => ("object".to_string(), serde_json::json!({})),
+            Selector::Other(kind, config) => (kind, config),
+        };
+        std::iter::once((kind, config)).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NumberSelector {
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    /// A number, or `"any"`.
+    #[serde(default)]
+    pub step: Option<serde_json::Value>,
+    #[serde(default)]
+    pub unit_of_measurement: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TextSelector {
+    #[serde(default)]
+    pub multiline: bool,
+    #[serde(default)]
+    pub multiple: bool,
+    #[serde(default, rename = "type")]
+    pub input_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SelectSelector {
+    #[serde(default)]
+    pub options: Vec<SelectOption>,
+    #[serde(default)]
+    pub multiple: bool,
+    /// Whether values outside of `options` are allowed.
+    #[serde(default)]
+    pub custom_value: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum SelectOption {
+    Value(String),
+    Labeled { value: String, label: String },
+}
+
+impl SelectOption {
+    pub fn value(&self) -> &str {
+        match self {
+            SelectOption::Value(value) => value,
+            SelectOption::Labeled { value, .. } => value,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EntitySelector {
+    #[serde(default)]
+    pub multiple: bool,
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub filter: Vec<EntityFilter>,
+}
+
+/// Accepts a single value where HA allows either one or a list.
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+
+    Ok(match Option::<OneOrMany<T>>::deserialize(deserializer)? {
+        Some(OneOrMany::Many(values)) => values,
+        Some(OneOrMany::One(value)) => vec![value],
+        None => Vec::new(),
+    })
+}
+
+/// A state of an entity at one point of its history.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HistoryState {
+    pub state: String,
+    /// `None` when the query asked HA to leave the attributes out.
+    pub attributes: Option<std::collections::HashMap<String, serde_json::Value>>,
+    pub last_changed: chrono::DateTime<chrono::Utc>,
+    pub last_updated: chrono::DateTime<chrono::Utc>,
+}
+
+/// An entity id such as `light.kitchen`, checked to be a valid `domain.object_id`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct EntityId(String);
+
+impl EntityId {
+    pub fn new(entity_id: impl Into<String>) -> Result<Self, crate::errors::Error> {
+        let entity_id = entity_id.into();
+        // the same rules as HA: lowercase letters, digits and single underscores on both
+        // sides of one dot, without underscores at their ends
+        let valid_part = |part: &str| {
+            !part.is_empty()
+                && part
+                    .bytes()
+                    .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+                && !part.starts_with('_')
+                && !part.ends_with('_')
+        };
+        match entity_id.split_once('.') {
+            Some((domain, object_id))
+                if valid_part(domain) && valid_part(object_id) && !entity_id.contains("__") =>
+            {
+                Ok(Self(entity_id))
+            }
+            _ => Err(crate::errors::Error::InvalidEntityId(entity_id)),
+        }
+    }
+
+    /// The part before the dot, such as `light`.
+    pub fn domain(&self) -> &str {
+        self.0.split('.').next().unwrap_or_default()
+    }
+
+    /// The part after the dot, such as `kitchen`.
+    pub fn object_id(&self) -> &str {
+        self.0.split('.').nth(1).unwrap_or_default()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The id percent-encoded for use as one segment of a URL path.
+    pub fn path_segment(&self) -> String {
+        percent_encoding::utf8_percent_encode(&self.0, percent_encoding::NON_ALPHANUMERIC)
+            .to_string()
+            // dots and underscores are safe in paths and keep the id readable
+            .replace("%2E", ".")
+            .replace("%5F", "_")
+    }
+}
+
+impl std::fmt::Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::str::FromStr for EntityId {
+    type Err = crate::errors::Error;
+
+    fn from_str(entity_id: &str) -> Result<Self, Self::Err> {
+        Self::new(entity_id)
+    }
+}
+
+impl std::convert::TryFrom<String> for EntityId {
+    type Error = crate::errors::Error;
+
+    fn try_from(entity_id: String) -> Result<Self, Self::Error> {
+        Self::new(entity_id)
+    }
+}
+
+impl std::convert::TryFrom<&str> for EntityId {
+    type Error = crate::errors::Error;
+
+    fn try_from(entity_id: &str) -> Result<Self, Self::Error> {
+        Self::new(entity_id)
+    }
+}
+
+impl From<EntityId> for String {
+    fn from(entity_id: EntityId) -> Self {
+        entity_id.0
+    }
+}
+
+impl AsRef<str> for EntityId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for EntityId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for EntityId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct StateObject {
-    pub attributes: std::collections::HashMap<String, String>,
+    pub attributes: std::collections::HashMap<String, serde_json::Value>,
     pub entity_id: String,
-    pub last_changed: String,
-    pub last_updated: Option<String>,
+    /// When `state` last changed.
+    pub last_changed: chrono::DateTime<chrono::Utc>,
+    /// When `state` or `attributes` last changed.
+    pub last_updated: chrono::DateTime<chrono::Utc>,
+    /// When the state was last written, even without changes. Only sent by HA 2024.3 and
+    /// later.
+    #[serde(default)]
+    pub last_reported: Option<chrono::DateTime<chrono::Utc>>,
     pub state: String,
+    #[serde(default)]
+    pub context: Option<Context>,
+}
+
+impl StateObject {
+    /// The domain of the entity, such as `light` for `light.kitchen`.
+    pub fn domain(&self) -> &str {
+        self.entity_id.split('.').next().unwrap_or_default()
+    }
+
+    /// The attribute `name` decoded as `T`, `None` when it is missing or of another type.
+    pub fn attribute<T: serde::de::DeserializeOwned>(&self, name: &str) -> Option<T> {
+        serde_json::from_value(self.attributes.get(name)?.clone()).ok()
+    }
+
+    pub fn attribute_str(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name)?.as_str()
+    }
+
+    pub fn friendly_name(&self) -> Option<&str> {
+        self.attribute_str("friendly_name")
+    }
+
+    pub fn unit_of_measurement(&self) -> Option<&str> {
+        self.attribute_str("unit_of_measurement")
+    }
+
+    pub fn device_class(&self) -> Option<&str> {
+        self.attribute_str("device_class")
+    }
+
+    pub fn icon(&self) -> Option<&str> {
+        self.attribute_str("icon")
+    }
+
+    /// The feature bits of the entity, 0 when it does not report any.
+    pub fn supported_features(&self) -> u32 {
+        self.attributes
+            .get("supported_features")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or_default() as u32
+    }
+
+    /// The state parsed as a number, `None` for states such as `unavailable`.
+    pub fn numeric_state(&self) -> Option<f64> {
+        self.state.parse().ok()
+    }
+
+    /// Whether HA has no state for the entity, because it is `unavailable` or `unknown`.
+    pub fn is_unavailable(&self) -> bool {
+        self.state == "unavailable" || self.state == "unknown"
+    }
+}
+
+/// A message of `subscribe_entities`, the first one adds every entity and later ones only
+/// carry what changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EntitiesDiff {
+    #[serde(rename = "a", default)]
+    pub added: std::collections::HashMap<String, CompressedState>,
+    #[serde(rename = "c", default)]
+    pub changed: std::collections::HashMap<String, CompressedStateDiff>,
+    #[serde(rename = "r", default)]
+    pub removed: Vec<String>,
+}
+
+/// A state in the short key format of `subscribe_entities`, timestamps are in seconds
+/// since the epoch.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompressedState {
+    #[serde(rename = "s")]
+    pub state: String,
+    #[serde(rename = "a", default)]
+    pub attributes: std::collections::HashMap<String, serde_json::Value>,
+    #[serde(rename = "c")]
+    pub context: CompressedContext,
+    #[serde(rename = "lc")]
+    pub last_changed: f64,
+    /// Only sent when it differs from `last_changed`.
+    #[serde(rename = "lu")]
+    pub last_updated: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CompressedStateDiff {
+    #[serde(rename = "+")]
+    pub additions: Option<CompressedStateChanges>,
+    #[serde(rename = "-")]
+    pub removals: Option<CompressedStateRemovals>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CompressedStateChanges {
+    #[serde(rename = "s")]
+    pub state: Option<String>,
+    /// Attributes that were added or changed.
+    #[serde(rename = "a", default)]
+    pub attributes: std::collections::HashMap<String, serde_json::Value>,
+    #[serde(rename = "c")]
+    pub context: Option<CompressedContext>,
+    #[serde(rename = "lc")]
+    pub last_changed: Option<f64>,
+    #[serde(rename = "lu")]
+    pub last_updated: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CompressedStateRemovals {
+    /// Names of the attributes that were removed.
+    #[serde(rename = "a", default)]
+    pub attributes: Vec<String>,
+}
+
+/// HA sends only the id of a context that has neither a parent nor a user.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum CompressedContext {
+    Id(String),
+    Full(Context),
+}
+
+impl From<CompressedContext> for Context {
+    fn from(context: CompressedContext) -> Self {
+        match context {
+            CompressedContext::Id(id) => Context {
+                id,
+                parent_id: None,
+                user_id: None,
+            },
+            CompressedContext::Full(context) => context,
+        }
+    }
+}
+
+/// Delivered by `render_template` on every render, or when rendering fails.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TemplateUpdate {
+    Rendered(RenderedTemplate),
+    Error(TemplateError),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RenderedTemplate {
+    /// The rendered template, HA parses results that look like numbers, lists or objects.
+    pub result: serde_json::Value,
+    pub listeners: TemplateListeners,
+}
+
+/// What causes the template to be rendered again.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TemplateListeners {
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default)]
+    pub domains: Vec<String>,
+    #[serde(default)]
+    pub entities: Vec<String>,
+    /// Set for templates using `now()`, which are rendered every minute.
+    #[serde(default)]
+    pub time: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TemplateError {
+    pub error: String,
+    /// `ERROR` or `WARNING`.
+    pub level: String,
+}
+
+/// Delivered by `subscribe_trigger` every time the trigger fires.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TriggerEvent {
+    pub variables: TriggerVariables,
+    pub context: Option<Context>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TriggerVariables {
+    pub trigger: TriggerData,
+    /// The variables passed to `subscribe_trigger`.
+    #[serde(flatten)]
+    pub variables: std::collections::HashMap<String, serde_json::Value>,
+}
+
+/// Describes why a trigger fired, fields that only some platforms set are in `other`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TriggerData {
+    pub platform: String,
+    pub id: String,
+    pub idx: String,
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub description: String,
+    pub entity_id: Option<String>,
+    pub from_state: Option<StateObject>,
+    pub to_state: Option<StateObject>,
+    /// Set by event triggers.
+    pub event: Option<Event>,
+    #[serde(flatten)]
+    pub other: std::collections::HashMap<String, serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -163,3 +913,424 @@ pub struct CheckConfig {
     pub errors: String,
     pub result: String,
 }
+
+/// An entity of the entity registry, `get_entity` also fills the fields marked as such.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EntityRegistryEntry {
+    pub entity_id: String,
+    pub id: String,
+    pub platform: String,
+    #[serde(default)]
+    pub unique_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub original_name: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub area_id: Option<String>,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub config_entry_id: Option<String>,
+    #[serde(default)]
+    pub disabled_by: Option<String>,
+    #[serde(default)]
+    pub hidden_by: Option<String>,
+    #[serde(default)]
+    pub entity_category: Option<String>,
+    #[serde(default)]
+    pub has_entity_name: bool,
+    #[serde(default)]
+    pub translation_key: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Only returned by `get_entity`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Only returned by `get_entity`.
+    #[serde(default)]
+    pub device_class: Option<String>,
+    /// Only returned by `get_entity`.
+    #[serde(default)]
+    pub original_device_class: Option<String>,
+    /// Only returned by `get_entity`.
+    #[serde(default)]
+    pub capabilities: Option<serde_json::Value>,
+}
+
+impl EntityRegistryEntry {
+    /// The name shown in HA, `None` when the entity is named after its device.
+    pub fn display_name(&self) -> Option<&str> {
+        self.name.as_deref().or(self.original_name.as_deref())
+    }
+
+    /// The state of this entity among `states`.
+    pub fn state<'a>(&self, states: &'a [StateObject]) -> Option<&'a StateObject> {
+        states
+            .iter()
+            .find(|state| state.entity_id == self.entity_id)
+    }
+
+    /// The area of this entity, which falls back to the area of its device.
+    pub fn area_id<'a>(&'a self, devices: &'a [DeviceRegistryEntry]) -> Option<&'a str> {
+        if let Some(area_id) = &self.area_id {
+            return Some(area_id);
+        }
+        let device_id = self.device_id.as_ref()?;
+        devices
+            .iter()
+            .find(|device| &device.id == device_id)
+            .and_then(|device| device.area_id.as_deref())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeviceRegistryEntry {
+    pub id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub name_by_user: Option<String>,
+    #[serde(default)]
+    pub area_id: Option<String>,
+    #[serde(default)]
+    pub manufacturer: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub sw_version: Option<String>,
+    #[serde(default)]
+    pub hw_version: Option<String>,
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    #[serde(default)]
+    pub configuration_url: Option<String>,
+    #[serde(default)]
+    pub config_entries: Vec<String>,
+    /// Pairs of connection type and id, such as `("mac", "aa:bb:cc:dd:ee:ff")`.
+    #[serde(default)]
+    pub connections: Vec<(String, String)>,
+    /// Pairs of integration domain and the id of the device within it.
+    #[serde(default)]
+    pub identifiers: Vec<(String, String)>,
+    #[serde(default)]
+    pub disabled_by: Option<String>,
+    #[serde(default)]
+    pub entry_type: Option<String>,
+    #[serde(default)]
+    pub via_device_id: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl DeviceRegistryEntry {
+    /// The name given by the user, or else the one reported by the integration.
+    pub fn display_name(&self) -> Option<&str> {
+        self.name_by_user.as_deref().or(self.name.as_deref())
+    }
+
+    /// The entities of this device among `entities`.
+    pub fn entities<'a>(
+        &'a self,
+        entities: &'a [EntityRegistryEntry],
+    ) -> impl Iterator<Item = &'a EntityRegistryEntry> {
+        entities
+            .iter()
+            .filter(move |entity| entity.device_id.as_ref() == Some(&self.id))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AreaRegistryEntry {
+    pub area_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub floor_id: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub picture: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub temperature_entity_id: Option<String>,
+    #[serde(default)]
+    pub humidity_entity_id: Option<String>,
+}
+
+/// Changes for `update_entity`, fields left as `None` are kept and `Some(None)` clears them.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct EntityRegistryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub area_id: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_class: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_by: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hidden_by: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_entity_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+}
+
+/// Changes for `update_device`, fields left as `None` are kept and `Some(None)` clears them.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DeviceRegistryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_by_user: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub area_id: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_by: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+}
+
+/// Changes for `update_area`, fields left as `None` are kept and `Some(None)` clears them.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct AreaRegistryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub floor_id: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub picture: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FloorRegistryEntry {
+    pub floor_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Storey of the floor, 0 for the ground floor and negative for basements.
+    #[serde(default)]
+    pub level: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LabelRegistryEntry {
+    pub label_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub icon: Option<String>,
+}
+
+/// Fields for `create_floor` and `update_floor`, fields left as `None` are kept and
+/// `Some(None)` clears them.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct FloorRegistryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub level: Option<Option<i32>>,
+}
+
+/// Fields for `create_label` and `update_label`, fields left as `None` are kept and
+/// `Some(None)` clears them.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct LabelRegistryUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icon: Option<Option<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistryAction {
+    Create,
+    Update,
+    Remove,
+    #[serde(other)]
+    Other,
+}
+
+/// Data of `entity_registry_updated` events.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntityRegistryUpdated {
+    pub action: RegistryAction,
+    pub entity_id: String,
+    /// The previous id when an update renamed the entity.
+    #[serde(default)]
+    pub old_entity_id: Option<String>,
+    /// The previous values of the fields an update changed.
+    #[serde(default)]
+    pub changes: std::collections::HashMap<String, serde_json::Value>,
+}
+
+/// Data of `device_registry_updated` events.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceRegistryUpdated {
+    pub action: RegistryAction,
+    pub device_id: String,
+    /// The previous values of the fields an update changed.
+    #[serde(default)]
+    pub changes: std::collections::HashMap<String, serde_json::Value>,
+}
+
+/// Data of `area_registry_updated` events.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AreaRegistryUpdated {
+    pub action: RegistryAction,
+    #[serde(default)]
+    pub area_id: Option<String>,
+}
+
+/// The length of the buckets long-term statistics are grouped in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatisticsPeriod {
+    #[serde(rename = "5minute")]
+    FiveMinutes,
+    #[serde(rename = "hour")]
+    Hour,
+    #[serde(rename = "day")]
+    Day,
+    #[serde(rename = "week")]
+    Week,
+    #[serde(rename = "month")]
+    Month,
+}
+
+/// A column of statistics rows, HA only returns the requested ones.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatisticType {
+    Change,
+    LastReset,
+    Max,
+    Mean,
+    Min,
+    State,
+    Sum,
+}
+
+/// The kinds of statistics `list_statistic_ids` can be narrowed to, HA rejects any other.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatisticIdType {
+    Mean,
+    Sum,
+}
+
+/// One bucket of long-term statistics, `mean`, `min` and `max` are set for measurements and
+/// `state`, `sum` and `change` for counters such as energy meters.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatisticsRow {
+    #[serde(deserialize_with = "timestamp_millis")]
+    pub start: chrono::DateTime<chrono::Utc>,
+    #[serde(deserialize_with = "timestamp_millis")]
+    pub end: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub mean: Option<f64>,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub state: Option<f64>,
+    #[serde(default)]
+    pub sum: Option<f64>,
+    /// How much `sum` grew during the bucket.
+    #[serde(default)]
+    pub change: Option<f64>,
+    #[serde(default, deserialize_with = "optional_timestamp_millis")]
+    pub last_reset: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// What HA records long-term statistics of, as returned by `list_statistic_ids` and
+/// `statistics_metadata`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatisticMetadata {
+    pub statistic_id: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `recorder` for entities, or the integration that imported the statistics.
+    pub source: String,
+    #[serde(default)]
+    pub has_mean: bool,
+    #[serde(default)]
+    pub has_sum: bool,
+    /// The unit the statistics are stored in.
+    #[serde(default)]
+    pub statistics_unit_of_measurement: Option<String>,
+    /// The unit the entity is displayed in, only returned by `list_statistic_ids`.
+    #[serde(default)]
+    pub display_unit_of_measurement: Option<String>,
+    /// Such as `energy` or `temperature`, the key to convert units with.
+    #[serde(default)]
+    pub unit_class: Option<String>,
+}
+
+/// HA sends statistics timestamps as milliseconds since the epoch, older versions as
+/// RFC 3339 strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Timestamp {
+    Millis(f64),
+    Rfc3339(chrono::DateTime<chrono::Utc>),
+}
+
+impl Timestamp {
+    fn into_datetime<E: serde::de::Error>(self) -> Result<chrono::DateTime<chrono::Utc>, E> {
+        use chrono::TimeZone;
+
+        match self {
+            Timestamp::Millis(millis) => chrono::Utc
+                .timestamp_millis_opt(millis.round() as i64)
+                .single()
+                .ok_or_else(|| E::custom(format!("timestamp out of range: {}", millis))),
+            Timestamp::Rfc3339(time) => Ok(time),
+        }
+    }
+}
+
+fn timestamp_millis<'de, D>(deserializer: D) -> Result<chrono::DateTime<chrono::Utc>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Timestamp::deserialize(deserializer)?.into_datetime()
+}
+
+fn optional_timestamp_millis<'de, D>(
+    deserializer: D,
+) -> Result<Option<chrono::DateTime<chrono::Utc>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::<Timestamp>::deserialize(deserializer)?
+        .map(Timestamp::into_datetime)
+        .transpose()
+}
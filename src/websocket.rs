@@ -0,0 +1,933 @@
+use crate::errors;
+use crate::mirror::StateMirror;
+use crate::registry::Registry;
+use crate::request;
+use crate::statistics::StatisticsQuery;
+use crate::trigger::Trigger;
+use crate::types;
+use futures::stream::SplitSink;
+use futures::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::stream::Stream;
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+type Socket = WebSocketStream<Stream<TcpStream, tokio_tls::TlsStream<TcpStream>>>;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    AuthRequired,
+    AuthOk {
+        #[serde(default)]
+        ha_version: String,
+    },
+    AuthInvalid {
+        message: String,
+    },
+    Result {
+        id: u64,
+        success: bool,
+        result: Option<serde_json::Value>,
+        error: Option<CommandError>,
+    },
+    Event {
+        id: u64,
+        event: serde_json::Value,
+    },
+    Pong {
+        id: u64,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CommandError {
+    code: String,
+    message: String,
+}
+
+type Reply = oneshot::Sender<Result<(u64, serde_json::Value), errors::Error>>;
+type EventSender = mpsc::UnboundedSender<Delivery<serde_json::Value>>;
+
+/// What the connection task delivers to a subscription.
+#[derive(Debug)]
+pub(crate) enum Delivery<T> {
+    Event(T),
+    /// The subscription was issued again after reconnecting, HA sends the initial
+    /// state of e.g. `subscribe_entities` again.
+    Resubscribed,
+}
+
+/// Work for the connection task, which assigns the ids of outgoing commands.
+enum Request {
+    Command {
+        message: serde_json::Value,
+        reply: Reply,
+    },
+    Subscribe {
+        message: serde_json::Value,
+        reply: Reply,
+        events: EventSender,
+    },
+    Unsubscribe(u64),
+    WatchState(mpsc::UnboundedSender<ConnectionState>),
+}
+
+/// A command sent on the current connection that has not been answered yet.
+enum PendingCommand {
+    Command(Reply),
+    Subscribe {
+        message: serde_json::Value,
+        reply: Reply,
+        events: EventSender,
+    },
+    /// Re-issue of the subscription with the given key after reconnecting.
+    Replay(u64),
+    KeepAlive,
+}
+
+/// A subscription kept alive across reconnections.
+struct ActiveSubscription {
+    message: serde_json::Value,
+    events: EventSender,
+    /// Id of the subscription on the current connection, if it was issued already.
+    id: Option<u64>,
+}
+
+/// State of the connection behind a `WebSocket`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// The connection was lost, commands are held back until it is reestablished.
+    Disconnected,
+    /// Waiting before the given reconnection attempt, counting from 1.
+    Reconnecting(u32),
+}
+
+/// Client for the websocket api at `/api/websocket`.
+///
+/// A background task owns the connection and matches results to commands by their id,
+/// so clones can send commands concurrently. Lost connections are reestablished with
+/// exponential backoff and subscriptions are issued again, the connection is closed once
+/// every clone and subscription is dropped.
+#[derive(Debug, Clone)]
+pub struct WebSocket {
+    requests: mpsc::UnboundedSender<Request>,
+    ha_version: String,
+}
+
+/// Stream of the events HA sends for a subscription, unsubscribes when dropped.
+///
+/// The stream ends when the connection is lost for good, e.g. because the refresh token
+/// was revoked.
+#[derive(Debug)]
+pub struct Subscription<T> {
+    id: u64,
+    events: mpsc::UnboundedReceiver<Delivery<serde_json::Value>>,
+    requests: mpsc::UnboundedSender<Request>,
+    event_type: PhantomData<fn() -> T>,
+}
+
+/// Stream of the state changes of a websocket connection.
+#[derive(Debug)]
+pub struct ConnectionStates {
+    states: mpsc::UnboundedReceiver<ConnectionState>,
+}
+
+impl WebSocket {
+    /// Connects and authenticates with the current access token of `ha_client`.
+    pub async fn connect(ha_client: &crate::HomeAssistantAPI) -> Result<Self, errors::Error> {
+        let access_token = ha_client.valid_access_token().await?;
+        let (socket, ha_version) = connect(ha_client, &access_token).await?;
+        let (requests, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(Connection::new(ha_client.clone(), receiver).run(socket));
+
+        Ok(Self {
+            requests,
+            ha_version,
+        })
+    }
+
+    /// Version of the HA instance, as reported when connecting.
+    pub fn ha_version(&self) -> &str {
+        &self.ha_version
+    }
+
+    /// Sends a command such as `{"type": "get_states"}` and decodes its result, the `id`
+    /// is filled in.
+    ///
+    /// Commands sent while reconnecting wait for the new connection, commands in flight
+    /// when the connection is lost fail.
+    pub async fn command<T: DeserializeOwned>(
+        &self,
+        message: serde_json::Value,
+    ) -> Result<T, errors::Error> {
+        let (reply, result) = oneshot::channel();
+        self.send(Request::Command { message, reply })?;
+        let (_, result) = receive_reply(result).await?;
+        request::decode_value(result)
+    }
+
+    /// Checks that the connection is still alive.
+    pub async fn ping(&self) -> Result<(), errors::Error> {
+        self.command::<serde_json::Value>(serde_json::json!({ "type": "ping" }))
+            .await?;
+        Ok(())
+    }
+
+    /// Calls `domain.service` on `target`, returning the context of the call.
+    pub async fn call_service(
+        &self,
+        domain: &str,
+        service: &str,
+        service_data: Option<serde_json::Value>,
+        target: Option<types::ServiceTarget>,
+    ) -> Result<types::Context, errors::Error> {
+        let message = call_service_message(domain, service, service_data, target, false);
+        let result: types::ServiceCallResult<Option<serde_json::Value>> =
+            self.command(message).await?;
+        Ok(result.context)
+    }
+
+    /// Calls a service that returns data, such as `weather.get_forecasts`, and decodes its
+    /// response into `T`.
+    pub async fn call_service_with_response<T: DeserializeOwned>(
+        &self,
+        domain: &str,
+        service: &str,
+        service_data: Option<serde_json::Value>,
+        target: Option<types::ServiceTarget>,
+    ) -> Result<types::ServiceCallResult<T>, errors::Error> {
+        let message = call_service_message(domain, service, service_data, target, true);
+        self.command(message).await
+    }
+
+    /// Sends a subscribing command such as `{"type": "subscribe_events"}` and streams the
+    /// `event` of every message HA sends for it.
+    pub async fn subscribe<T: DeserializeOwned>(
+        &self,
+        message: serde_json::Value,
+    ) -> Result<Subscription<T>, errors::Error> {
+        let (sender, events) = mpsc::unbounded_channel();
+        let (reply, result) = oneshot::channel();
+        self.send(Request::Subscribe {
+            message,
+            reply,
+            events: sender,
+        })?;
+        let (id, _) = receive_reply(result).await?;
+
+        Ok(Subscription {
+            id,
+            events,
+            requests: self.requests.clone(),
+            event_type: PhantomData,
+        })
+    }
+
+    /// Streams the events fired on the HA event bus, or only those of `event_type`.
+    pub async fn subscribe_events(
+        &self,
+        event_type: Option<&str>,
+    ) -> Result<Subscription<types::Event>, errors::Error> {
+        let mut message = serde_json::json!({ "type": "subscribe_events" });
+        if let Some(event_type) = event_type {
+            message["event_type"] = event_type.into();
+        }
+        self.subscribe(message).await
+    }
+
+    /// Streams the variables of `trigger` every time it fires, `variables` are made
+    /// available to its templates.
+    pub async fn subscribe_trigger(
+        &self,
+        trigger: impl Into<Trigger>,
+        variables: Option<serde_json::Value>,
+    ) -> Result<Subscription<types::TriggerEvent>, errors::Error> {
+        let mut message = serde_json::json!({
+            "type": "subscribe_trigger",
+            "trigger": trigger.into(),
+        });
+        if let Some(variables) = variables {
+            message["variables"] = variables;
+        }
+        self.subscribe(message).await
+    }
+
+    /// Renders `template` and streams the result again whenever an entity it uses changes.
+    ///
+    /// With `report_errors`, rendering errors after the first render are streamed as
+    /// `TemplateUpdate::Error`. A template that fails right away, takes longer than
+    /// `timeout` or uses an undefined variable with `strict` fails the subscription.
+    pub async fn render_template(
+        &self,
+        template: &str,
+        variables: Option<serde_json::Value>,
+        timeout: Option<Duration>,
+        strict: bool,
+        report_errors: bool,
+    ) -> Result<Subscription<types::TemplateUpdate>, errors::Error> {
+        let mut message = serde_json::json!({
+            "type": "render_template",
+            "template": template,
+        });
+        // HA releases from before these options reject them, so they are only sent when set
+        if strict {
+            message["strict"] = true.into();
+        }
+        if report_errors {
+            message["report_errors"] = true.into();
+        }
+        if let Some(variables) = variables {
+            message["variables"] = variables;
+        }
+        if let Some(timeout) = timeout {
+            message["timeout"] = timeout.as_secs_f64().into();
+        }
+        self.subscribe(message).await
+    }
+
+    /// Streams the states of all entities, or only of `entity_ids`, in the compressed diff
+    /// format, see `state_mirror` for a local copy that applies them.
+    pub async fn subscribe_entities(
+        &self,
+        entity_ids: Option<Vec<String>>,
+    ) -> Result<Subscription<types::EntitiesDiff>, errors::Error> {
+        let mut message = serde_json::json!({ "type": "subscribe_entities" });
+        if let Some(entity_ids) = entity_ids {
+            message["entity_ids"] = entity_ids.into();
+        }
+        self.subscribe(message).await
+    }
+
+    /// Mirrors the states of all entities, or only of `entity_ids`, and keeps them up to
+    /// date. Returns once the initial states arrived.
+    pub async fn state_mirror(
+        &self,
+        entity_ids: Option<Vec<String>>,
+    ) -> Result<StateMirror, errors::Error> {
+        let subscription = self.subscribe_entities(entity_ids).await?;
+        StateMirror::start(subscription).await
+    }
+
+    /// Starts a query of the long-term statistics recorded since `start`.
+    pub fn statistics_during_period(
+        &self,
+        start: chrono::DateTime<chrono::Utc>,
+    ) -> StatisticsQuery {
+        StatisticsQuery::new(self.clone(), start)
+    }
+
+    /// Every statistic HA records, optionally only those with a `mean` or a `sum`.
+    pub async fn list_statistic_ids(
+        &self,
+        statistic_type: Option<types::StatisticIdType>,
+    ) -> Result<Vec<types::StatisticMetadata>, errors::Error> {
+        let mut message = serde_json::json!({ "type": "recorder/list_statistic_ids" });
+        if let Some(statistic_type) = statistic_type {
+            message["statistic_type"] = serde_json::json!(statistic_type);
+        }
+        self.command(message).await
+    }
+
+    /// The metadata of `statistic_ids`, or of every statistic when empty.
+    pub async fn statistics_metadata(
+        &self,
+        statistic_ids: &[&str],
+    ) -> Result<Vec<types::StatisticMetadata>, errors::Error> {
+        let mut message = serde_json::json!({ "type": "recorder/get_statistics_metadata" });
+        if !statistic_ids.is_empty() {
+            message["statistic_ids"] = serde_json::json!(statistic_ids);
+        }
+        self.command(message).await
+    }
+
+    /// Client for the HA registries over this connection.
+    pub fn registry(&self) -> Registry {
+        Registry::from(self.clone())
+    }
+
+    /// Streams every later change of the connection state.
+    pub fn connection_states(&self) -> ConnectionStates {
+        let (sender, states) = mpsc::unbounded_channel();
+        // once the connection task is gone the stream simply ends
+        let _ = self.send(Request::WatchState(sender));
+        ConnectionStates { states }
+    }
+
+    fn send(&self, request: Request) -> Result<(), errors::Error> {
+        self.requests
+            .send(request)
+            .map_err(|_| tungstenite::Error::ConnectionClosed.into())
+    }
+}
+
+fn call_service_message(
+    domain: &str,
+    service: &str,
+    service_data: Option<serde_json::Value>,
+    target: Option<types::ServiceTarget>,
+    return_response: bool,
+) -> serde_json::Value {
+    let mut message = serde_json::json!({
+        "type": "call_service",
+        "domain": domain,
+        "service": service,
+    });
+    // HA before 2023.7 rejects the key, so plain calls leave it out
+    if return_response {
+        message["return_response"] = true.into();
+    }
+    if let Some(service_data) = service_data {
+        message["service_data"] = service_data;
+    }
+    if let Some(target) = target {
+        message["target"] = serde_json::json!(target);
+    }
+    message
+}
+
+async fn receive_reply(
+    result: oneshot::Receiver<Result<(u64, serde_json::Value), errors::Error>>,
+) -> Result<(u64, serde_json::Value), errors::Error> {
+    // the sender is dropped without a reply when the connection task gives up
+    result
+        .await
+        .map_err(|_| tungstenite::Error::ConnectionClosed)?
+}
+
+impl<T: DeserializeOwned> Subscription<T> {
+    /// Like `next` but also reports when the subscription was issued again.
+    pub(crate) async fn next_delivery(&mut self) -> Option<Delivery<Result<T, errors::Error>>> {
+        futures::future::poll_fn(|cx| self.poll_delivery(cx)).await
+    }
+
+    fn poll_delivery(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Delivery<Result<T, errors::Error>>>> {
+        self.events.poll_recv(cx).map(|delivery| {
+            delivery.map(|delivery| match delivery {
+                Delivery::Event(event) => Delivery::Event(request::decode_value(event)),
+                Delivery::Resubscribed => Delivery::Resubscribed,
+            })
+        })
+    }
+}
+
+impl<T: DeserializeOwned> futures::Stream for Subscription<T> {
+    type Item = Result<T, errors::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.poll_delivery(cx) {
+                Poll::Ready(Some(Delivery::Event(event))) => return Poll::Ready(Some(event)),
+                Poll::Ready(Some(Delivery::Resubscribed)) => continue,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        let _ = self.requests.send(Request::Unsubscribe(self.id));
+    }
+}
+
+impl futures::Stream for ConnectionStates {
+    type Item = ConnectionState;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.states.poll_recv(cx)
+    }
+}
+
+/// Opens `/api/websocket` on the instance and authenticates with `access_token`,
+/// returning the socket and the HA version.
+async fn connect(
+    ha_client: &crate::HomeAssistantAPI,
+    access_token: &str,
+) -> Result<(Socket, String), errors::Error> {
+    let handshake = handshake(ha_client, access_token);
+    match ha_client.inner.websocket.handshake_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, handshake)
+            .await
+            .map_err(|e| errors::Error::Timeout(Box::new(e)))?,
+        None => handshake.await,
+    }
+}
+
+async fn handshake(
+    ha_client: &crate::HomeAssistantAPI,
+    access_token: &str,
+) -> Result<(Socket, String), errors::Error> {
+    let mut url = url::Url::parse(ha_client.instance_url())
+        .map_err(|e| errors::Error::Config(format!("Invalid instance url: {}", e)))?;
+    let tls = match url.scheme() {
+        "http" => false,
+        "https" => true,
+        scheme => {
+            return Err(errors::Error::Config(format!(
+                "Unsupported instance url scheme: {}",
+                scheme
+            )))
+        }
+    };
+    let host = url
+        .host_str()
+        .ok_or_else(|| errors::Error::Config(String::from("Instance url has no host")))?
+        .to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+    url.set_scheme(if tls { "wss" } else { "ws" })
+        .expect("http and ws urls are interchangeable");
+    url.set_path("/api/websocket");
+
+    let tcp = TcpStream::connect((host.as_str(), port));
+    let tcp = match ha_client.inner.websocket.connect_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, tcp)
+            .await
+            .map_err(|e| errors::Error::Timeout(Box::new(e)))??,
+        None => tcp.await?,
+    };
+    let stream = if tls {
+        let connector = tokio_tls::TlsConnector::from(ha_client.inner.tls.connector()?);
+        Stream::Tls(connector.connect(&host, tcp).await?)
+    } else {
+        Stream::Plain(tcp)
+    };
+    let (mut socket, _) = tokio_tungstenite::client_async(url.as_str(), stream).await?;
+
+    match receive(&mut socket).await? {
+        ServerMessage::AuthRequired => {}
+        other => {
+            return Err(errors::Error::HaApi(format!(
+                "Expected auth_required from HA websocket, got {:?}",
+                other
+            )))
+        }
+    }
+
+    let auth = serde_json::json!({ "type": "auth", "access_token": access_token });
+    socket.send(Message::Text(auth.to_string())).await?;
+
+    match receive(&mut socket).await? {
+        ServerMessage::AuthOk { ha_version } => Ok((socket, ha_version)),
+        ServerMessage::AuthInvalid { message } => Err(errors::Error::Unauthorized(message)),
+        other => Err(errors::Error::HaApi(format!(
+            "Expected auth_ok from HA websocket, got {:?}",
+            other
+        ))),
+    }
+}
+
+async fn receive<S>(socket: &mut S) -> Result<ServerMessage, errors::Error>
+where
+    S: futures::Stream<Item = Result<Message, tungstenite::Error>> + Unpin,
+{
+    while let Some(message) = socket.next().await {
+        if let Message::Text(text) = message? {
+            return request::decode(text);
+        }
+    }
+
+    Err(tungstenite::Error::ConnectionClosed.into())
+}
+
+/// The background task behind a `WebSocket`, it owns the connection and reconnects
+/// when it is lost.
+struct Connection {
+    ha_client: crate::HomeAssistantAPI,
+    requests: mpsc::UnboundedReceiver<Request>,
+    /// Requests received while reconnecting.
+    queued: VecDeque<Request>,
+    pending: HashMap<u64, PendingCommand>,
+    /// Active subscriptions by the id they were first issued with.
+    subscriptions: HashMap<u64, ActiveSubscription>,
+    /// Subscription keys by their id on the current connection.
+    subscription_ids: HashMap<u64, u64>,
+    state_listeners: Vec<mpsc::UnboundedSender<ConnectionState>>,
+    next_id: u64,
+}
+
+/// Why `Connection::serve` returned.
+enum Closed {
+    Lost,
+    /// Every `WebSocket` and `Subscription` was dropped.
+    Dropped,
+}
+
+impl Connection {
+    fn new(ha_client: crate::HomeAssistantAPI, requests: mpsc::UnboundedReceiver<Request>) -> Self {
+        Self {
+            ha_client,
+            requests,
+            queued: VecDeque::new(),
+            pending: HashMap::new(),
+            subscriptions: HashMap::new(),
+            subscription_ids: HashMap::new(),
+            state_listeners: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    async fn run(mut self, mut socket: Socket) {
+        loop {
+            if let Closed::Dropped = self.serve(socket).await {
+                return;
+            }
+            self.disconnected();
+
+            socket = match self.reconnect().await {
+                Some(socket) => socket,
+                None => return,
+            };
+            self.set_state(ConnectionState::Connected);
+        }
+    }
+
+    /// Handles requests and messages until the connection is lost or no longer needed.
+    async fn serve(&mut self, socket: Socket) -> Closed {
+        let (mut sink, mut stream) = socket.split();
+
+        // subscriptions made on an earlier connection
+        let replays: Vec<_> = self
+            .subscriptions
+            .iter()
+            .filter(|(_, subscription)| subscription.id.is_none())
+            .map(|(key, subscription)| (*key, subscription.message.clone()))
+            .collect();
+        for (key, message) in replays {
+            let id = self.next_id();
+            self.pending.insert(id, PendingCommand::Replay(key));
+            if send(&mut sink, id, message).await.is_err() {
+                return Closed::Lost;
+            }
+        }
+
+        let period = self.ha_client.inner.websocket.ping_interval;
+        let mut keepalive = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        let mut awaiting_pong = false;
+
+        loop {
+            while let Some(request) = self.queued.pop_front() {
+                if self.handle(&mut sink, request).await.is_err() {
+                    return Closed::Lost;
+                }
+            }
+
+            tokio::select! {
+                request = self.requests.recv() => match request {
+                    Some(request) => {
+                        if self.handle(&mut sink, request).await.is_err() {
+                            return Closed::Lost;
+                        }
+                    }
+                    None => {
+                        let _ = sink.close().await;
+                        return Closed::Dropped;
+                    }
+                },
+                message = receive(&mut stream) => match message {
+                    Ok(ServerMessage::Pong { id }) => match self.pending.remove(&id) {
+                        Some(PendingCommand::KeepAlive) => awaiting_pong = false,
+                        Some(PendingCommand::Command(reply)) => {
+                            let _ = reply.send(Ok((id, serde_json::Value::Null)));
+                        }
+                        _ => {}
+                    },
+                    Ok(message) => {
+                        if self.dispatch(&mut sink, message).await.is_err() {
+                            return Closed::Lost;
+                        }
+                    }
+                    Err(errors::Error::Decode(error, body)) => self.undecodable(error, body),
+                    Err(_) => return Closed::Lost,
+                },
+                _ = keepalive.tick() => {
+                    // the previous ping went unanswered for a whole interval
+                    if awaiting_pong {
+                        return Closed::Lost;
+                    }
+                    awaiting_pong = true;
+                    let id = self.next_id();
+                    self.pending.insert(id, PendingCommand::KeepAlive);
+                    let ping = serde_json::json!({ "type": "ping" });
+                    if send(&mut sink, id, ping).await.is_err() {
+                        return Closed::Lost;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn handle(
+        &mut self,
+        sink: &mut SplitSink<Socket, Message>,
+        request: Request,
+    ) -> Result<(), errors::Error> {
+        match request {
+            Request::Command { message, reply } => {
+                let id = self.next_id();
+                self.pending.insert(id, PendingCommand::Command(reply));
+                send(sink, id, message).await
+            }
+            Request::Subscribe {
+                message,
+                reply,
+                events,
+            } => {
+                let id = self.next_id();
+                let pending = PendingCommand::Subscribe {
+                    message: message.clone(),
+                    reply,
+                    events,
+                };
+                self.pending.insert(id, pending);
+                send(sink, id, message).await
+            }
+            Request::Unsubscribe(key) => {
+                let subscription = match self.subscriptions.remove(&key) {
+                    Some(subscription) => subscription,
+                    None => return Ok(()),
+                };
+                match subscription.id {
+                    Some(subscription_id) => {
+                        self.subscription_ids.remove(&subscription_id);
+                        self.unsubscribe(sink, subscription_id).await
+                    }
+                    // a replay in flight is unsubscribed once HA confirms it
+                    None => Ok(()),
+                }
+            }
+            Request::WatchState(listener) => {
+                self.state_listeners.push(listener);
+                Ok(())
+            }
+        }
+    }
+
+    async fn dispatch(
+        &mut self,
+        sink: &mut SplitSink<Socket, Message>,
+        message: ServerMessage,
+    ) -> Result<(), errors::Error> {
+        match message {
+            ServerMessage::Result {
+                id,
+                success,
+                result,
+                error,
+            } => {
+                let result = match (success, error) {
+                    (true, _) => Ok((id, result.unwrap_or(serde_json::Value::Null))),
+                    (false, Some(error)) => Err(errors::Error::Command(error.code, error.message)),
+                    (false, None) => Err(errors::Error::HaApi(String::from(
+                        "HA websocket command failed",
+                    ))),
+                };
+                match self.pending.remove(&id) {
+                    Some(PendingCommand::Command(reply)) => {
+                        let _ = reply.send(result);
+                    }
+                    Some(PendingCommand::Subscribe {
+                        message,
+                        reply,
+                        events,
+                    }) => {
+                        let subscribed = result.is_ok();
+                        if reply.send(result).is_err() {
+                            // the caller gave up before HA confirmed the subscription
+                            if subscribed {
+                                self.unsubscribe(sink, id).await?;
+                            }
+                        } else if subscribed {
+                            let subscription = ActiveSubscription {
+                                message,
+                                events,
+                                id: Some(id),
+                            };
+                            self.subscriptions.insert(id, subscription);
+                            self.subscription_ids.insert(id, id);
+                        }
+                    }
+                    Some(PendingCommand::Replay(key)) => match self.subscriptions.get_mut(&key) {
+                        Some(subscription) if result.is_ok() => {
+                            subscription.id = Some(id);
+                            self.subscription_ids.insert(id, key);
+                            let _ = subscription.events.send(Delivery::Resubscribed);
+                        }
+                        // HA no longer accepts the subscription, which ends its stream
+                        Some(_) => {
+                            self.subscriptions.remove(&key);
+                        }
+                        None if result.is_ok() => self.unsubscribe(sink, id).await?,
+                        None => {}
+                    },
+                    Some(PendingCommand::KeepAlive) | None => {}
+                }
+            }
+            ServerMessage::Event { id, event } => {
+                let subscription = self
+                    .subscription_ids
+                    .get(&id)
+                    .and_then(|key| self.subscriptions.get(key));
+                if let Some(subscription) = subscription {
+                    let _ = subscription.events.send(Delivery::Event(event));
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Fails the command a result that could not be decoded was meant for, other messages
+    /// HA sends that this client does not understand are skipped.
+    fn undecodable(&mut self, error: serde_json::Error, body: String) {
+        let id = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .filter(|message| message["type"] == "result")
+            .and_then(|message| message["id"].as_u64());
+        let id = match id {
+            Some(id) => id,
+            None => return,
+        };
+        match self.pending.remove(&id) {
+            Some(PendingCommand::Command(reply))
+            | Some(PendingCommand::Subscribe { reply, .. }) => {
+                let _ = reply.send(Err(errors::Error::Decode(error, body)));
+            }
+            // a replay stays unconfirmed and is issued again after the next reconnect
+            Some(pending) => {
+                self.pending.insert(id, pending);
+            }
+            None => {}
+        }
+    }
+
+    async fn unsubscribe(
+        &mut self,
+        sink: &mut SplitSink<Socket, Message>,
+        subscription_id: u64,
+    ) -> Result<(), errors::Error> {
+        let id = self.next_id();
+        let message = serde_json::json!({
+            "type": "unsubscribe_events",
+            "subscription": subscription_id,
+        });
+        send(sink, id, message).await
+    }
+
+    /// Fails the commands that were in flight and marks every subscription for replay.
+    fn disconnected(&mut self) {
+        for (_, pending) in self.pending.drain() {
+            match pending {
+                PendingCommand::Command(reply) | PendingCommand::Subscribe { reply, .. } => {
+                    let _ = reply.send(Err(tungstenite::Error::ConnectionClosed.into()));
+                }
+                PendingCommand::Replay(_) | PendingCommand::KeepAlive => {}
+            }
+        }
+        self.subscription_ids.clear();
+        for subscription in self.subscriptions.values_mut() {
+            subscription.id = None;
+        }
+        self.set_state(ConnectionState::Disconnected);
+    }
+
+    /// Reconnects with exponential backoff, giving up only when HA no longer accepts the
+    /// credentials or nothing uses the connection anymore.
+    async fn reconnect(&mut self) -> Option<Socket> {
+        let config = self.ha_client.inner.websocket.clone();
+        let mut delay = config.min_reconnect_delay;
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            self.set_state(ConnectionState::Reconnecting(attempt));
+            if !self.wait(delay).await {
+                return None;
+            }
+            delay = std::cmp::min(delay * 2, config.max_reconnect_delay);
+
+            match self.open().await {
+                Ok(socket) => return Some(socket),
+                Err(errors::Error::Refresh)
+                | Err(errors::Error::RefreshTokenRevoked)
+                | Err(errors::Error::NoAuth)
+                | Err(errors::Error::Unauthorized(_)) => return None,
+                Err(_) => continue,
+            }
+        }
+    }
+
+    /// Connects again, refreshing the token once if HA rejects it.
+    async fn open(&self) -> Result<Socket, errors::Error> {
+        let access_token = self.ha_client.valid_access_token().await?;
+        match connect(&self.ha_client, &access_token).await {
+            Ok((socket, _)) => Ok(socket),
+            Err(errors::Error::Unauthorized(_)) => {
+                self.ha_client.refresh_rejected(&access_token).await?;
+                let access_token = self.ha_client.valid_access_token().await?;
+                let (socket, _) = connect(&self.ha_client, &access_token).await?;
+                Ok(socket)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sleeps for `delay` while queueing requests, returns false once the connection is
+    /// no longer needed.
+    async fn wait(&mut self, delay: Duration) -> bool {
+        let mut sleep = tokio::time::delay_for(delay);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => return true,
+                request = self.requests.recv() => match request {
+                    // subscriptions are not issued while disconnected, so there is nothing
+                    // to tell HA
+                    Some(Request::Unsubscribe(key)) => {
+                        self.subscriptions.remove(&key);
+                    }
+                    Some(Request::WatchState(listener)) => self.state_listeners.push(listener),
+                    Some(request) => self.queued.push_back(request),
+                    None => return false,
+                },
+            }
+        }
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        self.state_listeners
+            .retain(|listener| listener.send(state).is_ok());
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+async fn send(
+    sink: &mut SplitSink<Socket, Message>,
+    id: u64,
+    mut message: serde_json::Value,
+) -> Result<(), errors::Error> {
+    message["id"] = id.into();
+    sink.send(Message::Text(message.to_string())).await?;
+    Ok(())
+}
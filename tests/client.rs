@@ -0,0 +1,152 @@
+mod common;
+
+use common::{bearer, serve};
+use homeassistant::errors::Error;
+use homeassistant::HomeAssistantAPI;
+use hyper::StatusCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test(threaded_scheduler)]
+async fn concurrent_requests_share_one_refresh() {
+    let refreshes = Arc::new(AtomicUsize::new(0));
+    let counter = refreshes.clone();
+    let url = serve(move |parts, _| match parts.uri.path() {
+        "/auth/token" => {
+            counter.fetch_add(1, Ordering::SeqCst);
+            // keep the refresh in flight long enough for every request to queue up
+            std::thread::sleep(Duration::from_millis(100));
+            (
+                StatusCode::OK,
+                r#"{"access_token":"fresh-access","expires_in":1800,"token_type":"Bearer"}"#
+                    .to_string(),
+            )
+        }
+        "/api/discovery_info" => {
+            assert_eq!(bearer(parts), "fresh-access");
+            (
+                StatusCode::OK,
+                r#"{"base_url":"http://hass.local:8123","location_name":"Home","requires_api_password":false,"version":"2024.1.0"}"#
+                    .to_string(),
+            )
+        }
+        other => panic!("unexpected request to {}", other),
+    })
+    .await;
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("stale-access".to_string(), 0, "stored-refresh".to_string())
+        .await
+        .unwrap();
+    let rest = api.get_rest_client().await;
+
+    let requests: Vec<_> = (0..8)
+        .map(|_| {
+            let rest = rest.clone();
+            tokio::spawn(async move { rest.discovery_info().await })
+        })
+        .collect();
+    for request in requests {
+        let info = request.await.unwrap().unwrap();
+        assert_eq!(info.location_name, "Home");
+    }
+
+    assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn clones_share_the_token() {
+    let api = HomeAssistantAPI::new(
+        "http://127.0.0.1:9".to_string(),
+        "http://client.example/".to_string(),
+    );
+    let clone = api.clone();
+
+    tokio::spawn(async move {
+        clone
+            .set_long_lived_token("long-lived".to_string())
+            .await
+            .unwrap()
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(api.token().await.as_string().unwrap(), "long-lived");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn builder_configures_the_shared_http_client() {
+    let url = serve(|parts, _| {
+        assert_eq!(parts.headers["User-Agent"], "halcyon/1.0");
+        if parts.uri.path() == "/api/error_log" {
+            std::thread::sleep(Duration::from_millis(500));
+        }
+        (StatusCode::OK, String::from("log"))
+    })
+    .await;
+
+    let api = HomeAssistantAPI::builder(url, "http://client.example/".to_string())
+        .user_agent("halcyon/1.0")
+        .timeout(Duration::from_millis(100))
+        .no_proxy()
+        .build()
+        .unwrap();
+    api.set_long_lived_token("long-lived".to_string())
+        .await
+        .unwrap();
+    let rest = api.get_rest_client().await;
+
+    assert_eq!(
+        rest.template_render("{{ 1 }}".to_string()).await.unwrap(),
+        "log"
+    );
+    match rest.error_log().await {
+        Err(Error::Timeout(_)) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn builder_rejects_invalid_root_certificates() {
+    let result = HomeAssistantAPI::builder(
+        "https://127.0.0.1:9".to_string(),
+        "http://client.example/".to_string(),
+    )
+    .add_root_certificate_pem(b"not a certificate")
+    .build();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn builder_rejects_invalid_websocket_settings() {
+    let builder = || {
+        HomeAssistantAPI::builder(
+            "http://127.0.0.1:9".to_string(),
+            "http://client.example/".to_string(),
+        )
+    };
+    let second = Duration::from_secs(1);
+
+    for result in [
+        builder()
+            .websocket_ping_interval(Duration::from_secs(0))
+            .build(),
+        builder()
+            .websocket_reconnect_delay(Duration::from_secs(0), second)
+            .build(),
+        builder()
+            .websocket_reconnect_delay(second * 2, second)
+            .build(),
+    ] {
+        match result {
+            Err(Error::Config(_)) => {}
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+    assert!(builder()
+        .websocket_reconnect_delay(second, second)
+        .build()
+        .is_ok());
+}
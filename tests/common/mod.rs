@@ -0,0 +1,147 @@
+#![allow(dead_code)]
+
+use futures::{SinkExt, StreamExt};
+use homeassistant::HomeAssistantAPI;
+use hyper::http::request::Parts;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde_json::json;
+use std::convert::Infallible;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Starts a stand-in Home Assistant that answers every request through `handler`,
+/// which receives the request head and body.
+pub async fn serve<F>(handler: F) -> String
+where
+    F: Fn(&Parts, String) -> (StatusCode, String) + Clone + Send + Sync + 'static,
+{
+    let make_svc = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let handler = handler.clone();
+                async move {
+                    let (parts, body) = req.into_parts();
+                    let body = hyper::body::to_bytes(body).await.unwrap();
+                    let body = String::from_utf8(body.to_vec()).unwrap();
+                    let (status, body) = handler(&parts, body);
+                    let response = Response::builder()
+                        .status(status)
+                        .body(Body::from(body))
+                        .unwrap();
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    url
+}
+
+/// A client for the stand-in at `url` that authenticates with the long lived token
+/// `access_token`.
+pub async fn api(url: String, access_token: &str) -> HomeAssistantAPI {
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_long_lived_token(access_token.to_string())
+        .await
+        .unwrap();
+    api
+}
+
+/// Returns the bearer token a request was sent with.
+pub fn bearer(parts: &Parts) -> &str {
+    parts
+        .headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default()
+}
+
+/// Starts a stand-in for `/api/websocket` that accepts the access token `access`
+/// and answers every command with the result of `handler`.
+pub async fn serve_websocket<F>(handler: F) -> String
+where
+    F: Fn(&serde_json::Value) -> serde_json::Value + Clone + Send + Sync + 'static,
+{
+    serve_websocket_messages(move |command| {
+        if command["type"] == "ping" {
+            return vec![json!({"id": command["id"], "type": "pong"})];
+        }
+        vec![json!({
+            "id": command["id"],
+            "type": "result",
+            "success": true,
+            "result": handler(command),
+        })]
+    })
+    .await
+}
+
+/// Like `serve_websocket` but `handler` returns every message to send back, a `null`
+/// message closes the connection.
+pub async fn serve_websocket_messages<F>(handler: F) -> String
+where
+    F: Fn(&serde_json::Value) -> Vec<serde_json::Value> + Clone + Send + Sync + 'static,
+{
+    let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let send = |value: serde_json::Value| Message::Text(value.to_string());
+
+                socket
+                    .send(send(
+                        json!({"type": "auth_required", "ha_version": "2024.1.0"}),
+                    ))
+                    .await
+                    .unwrap();
+                let auth = next_json(&mut socket).await.unwrap();
+                if auth != json!({"type": "auth", "access_token": "access"}) {
+                    let invalid =
+                        json!({"type": "auth_invalid", "message": "Invalid access token"});
+                    let _ = socket.send(send(invalid)).await;
+                    return;
+                }
+                socket
+                    .send(send(json!({"type": "auth_ok", "ha_version": "2024.1.0"})))
+                    .await
+                    .unwrap();
+
+                while let Some(command) = next_json(&mut socket).await {
+                    for reply in handler(&command) {
+                        if reply.is_null() {
+                            let _ = socket.close(None).await;
+                            return;
+                        }
+                        if socket.send(send(reply)).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    url
+}
+
+pub async fn next_json<S>(socket: &mut S) -> Option<serde_json::Value>
+where
+    S: futures::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    while let Some(Ok(message)) = socket.next().await {
+        if let Message::Text(text) = message {
+            return Some(serde_json::from_str(&text).unwrap());
+        }
+    }
+    None
+}
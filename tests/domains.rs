@@ -0,0 +1,170 @@
+mod common;
+
+use common::{api, serve};
+use homeassistant::domains::{
+    Climate, ClimateFeatures, Cover, CoverState, HvacMode, Light, LightFeatures, LightTurnOn,
+    MediaPlayer, MediaPlayerFeatures, Switch,
+};
+use homeassistant::errors::Error;
+use homeassistant::types::StateObject;
+use hyper::StatusCode;
+use serde_json::json;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn state(entity_id: &str, state: &str, attributes: serde_json::Value) -> StateObject {
+    serde_json::from_value(json!({
+        "entity_id": entity_id,
+        "state": state,
+        "attributes": attributes,
+        "last_changed": "2024-01-01T12:00:00+00:00",
+        "last_updated": "2024-01-01T12:00:00+00:00",
+    }))
+    .unwrap()
+}
+
+#[test]
+fn views_decode_attributes_and_features() {
+    let light = Light::try_from(state(
+        "light.kitchen",
+        "on",
+        json!({
+            "brightness": 128,
+            "rgb_color": [255, 0, 0],
+            "color_mode": "rgb",
+            "effect_list": ["none", "colorloop"],
+            "supported_features": 44,
+        }),
+    ))
+    .unwrap();
+    assert!(light.is_on());
+    assert_eq!(light.brightness(), Some(128));
+    assert_eq!(light.rgb_color(), Some([255, 0, 0]));
+    assert_eq!(light.effect_list(), ["none", "colorloop"]);
+    assert_eq!(
+        light.features(),
+        LightFeatures::EFFECT | LightFeatures::FLASH | LightFeatures::TRANSITION
+    );
+
+    let climate = Climate::try_from(state(
+        "climate.hall",
+        "heat_cool",
+        json!({
+            "hvac_modes": ["off", "heat", "heat_cool"],
+            "current_temperature": 19.5,
+            "target_temp_low": 18,
+            "target_temp_high": 23.5,
+            "supported_features": 386,
+        }),
+    ))
+    .unwrap();
+    assert_eq!(climate.hvac_mode(), Some(HvacMode::HeatCool));
+    assert_eq!(climate.hvac_modes()[1], HvacMode::Heat);
+    assert_eq!(climate.current_temperature(), Some(19.5));
+    assert_eq!(climate.target_temperature(), None);
+    assert_eq!(climate.target_temperature_range(), Some((18.0, 23.5)));
+    assert!(climate
+        .features()
+        .contains(ClimateFeatures::TARGET_TEMPERATURE_RANGE | ClimateFeatures::TURN_ON));
+
+    let cover = Cover::try_from(state("cover.garage", "unavailable", json!({}))).unwrap();
+    assert_eq!(cover.cover_state(), None);
+    assert!(cover.features().is_empty());
+    let cover = Cover::try_from(state(
+        "cover.garage",
+        "opening",
+        json!({"current_position": 40}),
+    ))
+    .unwrap();
+    assert_eq!(cover.cover_state(), Some(CoverState::Opening));
+    assert_eq!(cover.current_position(), Some(40));
+
+    let player = MediaPlayer::try_from(state(
+        "media_player.living_room",
+        "playing",
+        json!({"volume_level": 0.35, "is_volume_muted": false, "supported_features": 152_461}),
+    ))
+    .unwrap();
+    assert_eq!(player.volume_level(), Some(0.35));
+    assert!(player
+        .features()
+        .contains(MediaPlayerFeatures::PAUSE | MediaPlayerFeatures::PLAY));
+
+    match Switch::try_from(state("light.kitchen", "on", json!({}))) {
+        Err(Error::WrongDomain(domain, entity_id)) => {
+            assert_eq!(domain, "switch");
+            assert_eq!(entity_id, "light.kitchen");
+        }
+        other => panic!("expected a wrong domain, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn service_helpers_target_the_entity() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let received = calls.clone();
+    let url = serve(move |parts, body| {
+        received
+            .lock()
+            .unwrap()
+            .push((parts.uri.path().to_string(), body));
+        (StatusCode::OK, "[]".to_string())
+    })
+    .await;
+    let api = api(url, "long-lived").await;
+    let rest = api.get_rest_client().await;
+
+    let light = Light::try_from(state("light.kitchen", "off", json!({}))).unwrap();
+    light
+        .turn_on(
+            &rest,
+            LightTurnOn {
+                brightness_pct: Some(60),
+                transition: Some(Duration::from_millis(1500)),
+                ..LightTurnOn::default()
+            },
+        )
+        .await
+        .unwrap();
+    light
+        .turn_off(&rest, Some(Duration::from_millis(250)))
+        .await
+        .unwrap();
+    light.turn_off(&rest, None).await.unwrap();
+    let climate = Climate::try_from(state("climate.hall", "off", json!({}))).unwrap();
+    climate.set_hvac_mode(&rest, HvacMode::Heat).await.unwrap();
+    let cover = Cover::try_from(state("cover.garage", "closed", json!({}))).unwrap();
+    cover.set_position(&rest, 30).await.unwrap();
+
+    let calls = calls.lock().unwrap();
+    let bodies: Vec<(&str, serde_json::Value)> = calls
+        .iter()
+        .map(|(path, body)| (path.as_str(), serde_json::from_str(body).unwrap()))
+        .collect();
+    assert_eq!(
+        bodies,
+        vec![
+            (
+                "/api/services/light/turn_on",
+                json!({"entity_id": "light.kitchen", "brightness_pct": 60, "transition": 1.5}),
+            ),
+            (
+                "/api/services/light/turn_off",
+                json!({"entity_id": "light.kitchen", "transition": 0.25}),
+            ),
+            (
+                "/api/services/light/turn_off",
+                json!({"entity_id": "light.kitchen"}),
+            ),
+            (
+                "/api/services/climate/set_hvac_mode",
+                json!({"entity_id": "climate.hall", "hvac_mode": "heat"}),
+            ),
+            (
+                "/api/services/cover/set_cover_position",
+                json!({"entity_id": "cover.garage", "position": 30}),
+            ),
+        ]
+    );
+}
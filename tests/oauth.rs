@@ -0,0 +1,574 @@
+mod common;
+
+use common::{serve, serve_websocket};
+use homeassistant::errors::Error;
+use homeassistant::token_store::{FileTokenStore, MemoryTokenStore, TokenStore};
+use homeassistant::types::{LoginFlowStep, LoginFlowStepId};
+use homeassistant::{HomeAssistantAPI, Token};
+use hyper::StatusCode;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+/// Stand-in for the `/auth/token` endpoint that only accepts `valid_refresh_token`
+/// or the code `valid-code` and records every form body it receives.
+async fn serve_auth_token(valid_refresh_token: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+    let bodies = Arc::new(Mutex::new(Vec::new()));
+    let recorded = bodies.clone();
+
+    let url = serve(move |parts, body| {
+        assert_eq!(parts.uri, "/auth/token");
+        bodies.lock().unwrap().push(body.clone());
+        token_response(&body, valid_refresh_token)
+    })
+    .await;
+
+    (url, recorded)
+}
+
+fn token_response(body: &str, valid_refresh_token: &str) -> (StatusCode, String) {
+    let expected = format!("refresh_token={}", valid_refresh_token);
+    if body.split('&').any(|pair| pair == "code=valid-code") {
+        (
+            StatusCode::OK,
+            r#"{"access_token":"code-access","expires_in":1800,"refresh_token":"code-refresh","token_type":"Bearer"}"#.to_string(),
+        )
+    } else if body.split('&').any(|pair| pair == expected) {
+        (
+            StatusCode::OK,
+            r#"{"access_token":"fresh-access","expires_in":1800,"token_type":"Bearer"}"#
+                .to_string(),
+        )
+    } else {
+        (
+            StatusCode::BAD_REQUEST,
+            r#"{"error":"invalid_grant"}"#.to_string(),
+        )
+    }
+}
+
+#[tokio::test]
+async fn refresh_uses_stored_refresh_token() {
+    let (url, bodies) = serve_auth_token("stored-refresh").await;
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("stale-access".to_string(), 0, "stored-refresh".to_string())
+        .await
+        .unwrap();
+    assert!(api.token().await.need_refresh());
+
+    api.refresh_oauth_token().await.unwrap();
+
+    assert_eq!(api.token().await.as_string().unwrap(), "fresh-access");
+    assert!(!api.token().await.need_refresh());
+
+    let bodies = bodies.lock().unwrap();
+    assert_eq!(bodies.len(), 1);
+    assert!(bodies[0].contains("grant_type=refresh_token"));
+    assert!(bodies[0].contains("client_id=http%3A%2F%2Fclient.example%2F"));
+    assert!(bodies[0].contains("refresh_token=stored-refresh"));
+}
+
+#[tokio::test]
+async fn refresh_token_survives_repeated_refreshes() {
+    let (url, bodies) = serve_auth_token("stored-refresh").await;
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("stale-access".to_string(), 0, "stored-refresh".to_string())
+        .await
+        .unwrap();
+
+    api.refresh_oauth_token().await.unwrap();
+    api.refresh_oauth_token().await.unwrap();
+
+    assert_eq!(bodies.lock().unwrap().len(), 2);
+    match api.token().await {
+        Token::Oauth(token) => assert_eq!(token.refresh_token(), "stored-refresh"),
+        other => panic!("expected an oauth token, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn revoked_refresh_token_is_reported() {
+    let (url, _) = serve_auth_token("stored-refresh").await;
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("stale-access".to_string(), 0, "revoked-refresh".to_string())
+        .await
+        .unwrap();
+
+    match api.refresh_oauth_token().await {
+        Err(Error::RefreshTokenRevoked) => {}
+        other => panic!("expected a revoked refresh token error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn refreshing_a_long_lived_token_fails() {
+    let api = HomeAssistantAPI::new(
+        "http://127.0.0.1:9".to_string(),
+        "http://client.example/".to_string(),
+    );
+    api.set_long_lived_token("long-lived".to_string())
+        .await
+        .unwrap();
+
+    match api.refresh_oauth_token().await {
+        Err(Error::Refresh) => {}
+        other => panic!("expected a refresh error, got {:?}", other),
+    }
+}
+
+/// Plays the browser following Home Assistant's redirect back to the listener.
+fn redirect_browser(redirect_uri: &str, query: &[(&str, &str)]) {
+    let url = reqwest::Url::parse_with_params(redirect_uri, query).unwrap();
+    tokio::spawn(async move {
+        let _ = reqwest::get(url).await;
+    });
+}
+
+#[tokio::test]
+async fn login_exchanges_redirected_code() {
+    let (url, bodies) = serve_auth_token("stored-refresh").await;
+    let api = HomeAssistantAPI::new(url.clone(), "http://127.0.0.1/".to_string());
+
+    let listener = api
+        .login_listener(([127, 0, 0, 1], 0).into())
+        .await
+        .unwrap();
+    let authorize_url = reqwest::Url::parse(listener.authorize_url()).unwrap();
+    assert_eq!(authorize_url.path(), "/auth/authorize");
+    let params: Vec<(String, String)> = authorize_url.query_pairs().into_owned().collect();
+    assert!(params.contains(&("client_id".to_string(), "http://127.0.0.1/".to_string())));
+    assert!(params.contains(&(
+        "redirect_uri".to_string(),
+        listener.redirect_uri().to_string()
+    )));
+    assert!(params.contains(&("state".to_string(), listener.state().to_string())));
+
+    let state = listener.state().to_string();
+    redirect_browser(
+        listener.redirect_uri(),
+        &[("code", "valid-code"), ("state", state.as_str())],
+    );
+    let response = api.login(listener).await.unwrap();
+
+    assert_eq!(response.refresh_token, "code-refresh");
+    assert_eq!(api.token().await.as_string().unwrap(), "code-access");
+    assert!(bodies.lock().unwrap()[0].contains("grant_type=authorization_code"));
+}
+
+#[tokio::test]
+async fn login_rejects_mismatched_state() {
+    let api = HomeAssistantAPI::new(
+        "http://127.0.0.1:9".to_string(),
+        "http://127.0.0.1/".to_string(),
+    );
+
+    let listener = api
+        .login_listener(([127, 0, 0, 1], 0).into())
+        .await
+        .unwrap();
+    redirect_browser(
+        listener.redirect_uri(),
+        &[("code", "valid-code"), ("state", "forged")],
+    );
+
+    match api.login(listener).await {
+        Err(Error::StateMismatch) => {}
+        other => panic!("expected a state mismatch, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn login_ignores_idle_and_stray_connections() {
+    let (url, _) = serve_auth_token("stored-refresh").await;
+    let api = HomeAssistantAPI::new(url, "http://127.0.0.1/".to_string());
+
+    let listener = api
+        .login_listener(([127, 0, 0, 1], 0).into())
+        .await
+        .unwrap();
+    let redirect_uri = listener.redirect_uri().to_string();
+    let state = listener.state().to_string();
+    let addr = redirect_uri
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .to_string();
+    let login = tokio::spawn(async move { api.login(listener).await });
+
+    // a preconnected socket that never sends a request
+    let _idle = tokio::net::TcpStream::connect(addr.as_str()).await.unwrap();
+    let browser = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(5))
+        .build()
+        .unwrap();
+    let favicon = browser
+        .get(format!("{}favicon.ico", redirect_uri).as_str())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(favicon.status(), StatusCode::NOT_FOUND);
+    let bare = browser.get(redirect_uri.as_str()).send().await.unwrap();
+    assert_eq!(bare.status(), StatusCode::NOT_FOUND);
+    redirect_browser(
+        &redirect_uri,
+        &[("code", "valid-code"), ("state", state.as_str())],
+    );
+
+    let response = tokio::time::timeout(std::time::Duration::from_secs(5), login)
+        .await
+        .expect("the idle connection held up the login")
+        .unwrap()
+        .unwrap();
+    assert_eq!(response.refresh_token, "code-refresh");
+}
+
+#[tokio::test]
+async fn login_gives_up_after_its_timeout() {
+    let api = HomeAssistantAPI::new(
+        "http://127.0.0.1:9".to_string(),
+        "http://127.0.0.1/".to_string(),
+    );
+
+    let listener = api
+        .login_listener(([127, 0, 0, 1], 0).into())
+        .await
+        .unwrap()
+        .timeout(std::time::Duration::from_millis(100));
+    let addr = listener
+        .redirect_uri()
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .to_string();
+
+    match api.login(listener).await {
+        Err(Error::Timeout(_)) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+    // the port is released with the listener
+    std::net::TcpListener::bind(addr.as_str()).unwrap();
+}
+
+/// Stand-in for `/auth/login_flow` for a user with TOTP enabled, the flow hands
+/// out `valid-code` once the password `hunter2` and the mfa code `123456` are sent.
+async fn serve_login_flow() -> String {
+    serve(|parts, body| match parts.uri.path() {
+        "/auth/login_flow" => {
+            assert!(body.contains(r#""handler":["homeassistant",null]"#));
+            (
+                StatusCode::OK,
+                r#"{"type":"form","flow_id":"f1","handler":["homeassistant",null],"step_id":"init","data_schema":[{"type":"string","name":"username","required":true},{"type":"string","name":"password","required":true}],"errors":{},"description_placeholders":null,"last_step":null}"#.to_string(),
+            )
+        }
+        "/auth/login_flow/f1" if body.contains(r#""password":"hunter2""#) => (
+            StatusCode::OK,
+            r#"{"type":"form","flow_id":"f1","handler":["homeassistant",null],"step_id":"mfa","data_schema":[{"type":"string","name":"code"}],"errors":null,"description_placeholders":{"mfa_module_name":"Authenticator app"},"last_step":null}"#.to_string(),
+        ),
+        "/auth/login_flow/f1" if body.contains(r#""code":"123456""#) => (
+            StatusCode::OK,
+            r#"{"version":1,"type":"create_entry","flow_id":"f1","handler":["homeassistant",null],"result":"valid-code","description":null,"description_placeholders":null}"#.to_string(),
+        ),
+        "/auth/login_flow/f1" => (
+            StatusCode::OK,
+            r#"{"type":"form","flow_id":"f1","handler":["homeassistant",null],"step_id":"init","data_schema":[],"errors":{"base":"invalid_auth"},"description_placeholders":null,"last_step":null}"#.to_string(),
+        ),
+        "/auth/token" => token_response(&body, "unused"),
+        other => panic!("unexpected request to {}", other),
+    })
+    .await
+}
+
+#[tokio::test]
+async fn login_flow_walks_through_mfa() {
+    let url = serve_login_flow().await;
+    let api = HomeAssistantAPI::new(url, "http://127.0.0.1/".to_string());
+
+    let response = api
+        .login_with_credentials("user", "hunter2", Some("123456"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.refresh_token, "code-refresh");
+    assert_eq!(api.token().await.as_string().unwrap(), "code-access");
+}
+
+#[tokio::test]
+async fn login_flow_reports_typed_steps() {
+    let url = serve_login_flow().await;
+    let api = HomeAssistantAPI::new(url, "http://127.0.0.1/".to_string());
+
+    let mut flow = api.login_flow().await.unwrap();
+    assert_eq!(flow.flow_id(), "f1");
+
+    match flow.submit_credentials("user", "wrong").await.unwrap() {
+        LoginFlowStep::Form(form) => {
+            assert_eq!(form.step_id, LoginFlowStepId::Init);
+            assert_eq!(form.errors["base"], "invalid_auth");
+        }
+        other => panic!("expected the credentials form again, got {:?}", other),
+    }
+
+    match flow.submit_credentials("user", "hunter2").await.unwrap() {
+        LoginFlowStep::Form(form) => {
+            assert_eq!(form.step_id, LoginFlowStepId::Mfa);
+            assert!(form.errors.is_empty());
+        }
+        other => panic!("expected the mfa form, got {:?}", other),
+    }
+
+    flow.submit_mfa_code("123456").await.unwrap();
+    assert_eq!(flow.code(), Some("valid-code"));
+}
+
+fn temp_token_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "homeassistant-{}-{}.json",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn refreshed_token_is_persisted_and_restored() {
+    let (url, _) = serve_auth_token("stored-refresh").await;
+    let path = temp_token_path("refresh");
+
+    {
+        let api = HomeAssistantAPI::new(url.clone(), "http://client.example/".to_string());
+        api.set_token_store(Box::new(FileTokenStore::new(&path)))
+            .await
+            .unwrap();
+        api.set_oauth_token("stale-access".to_string(), 0, "stored-refresh".to_string())
+            .await
+            .unwrap();
+        api.refresh_oauth_token().await.unwrap();
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_token_store(Box::new(FileTokenStore::new(&path)))
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(api.token().await.as_string().unwrap(), "fresh-access");
+    assert!(!api.token().await.need_refresh());
+    match api.token().await {
+        Token::Oauth(token) => assert_eq!(token.refresh_token(), "stored-refresh"),
+        other => panic!("expected an oauth token, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn memory_store_keeps_the_last_token() {
+    let store = MemoryTokenStore::new();
+    assert!(store.load().unwrap().is_none());
+
+    let api = HomeAssistantAPI::new(
+        "http://127.0.0.1:9".to_string(),
+        "http://client.example/".to_string(),
+    );
+    api.set_long_lived_token("long-lived".to_string())
+        .await
+        .unwrap();
+    store.save(&api.token().await).unwrap();
+
+    let restored = store.load().unwrap().unwrap();
+    assert!(matches!(restored, Token::LongLived(_)));
+    assert_eq!(restored.as_string().unwrap(), "long-lived");
+}
+
+/// A store with a slow disk, every save takes a while.
+#[derive(Debug, Default)]
+struct SlowTokenStore {
+    saved: Arc<Mutex<Vec<String>>>,
+}
+
+impl TokenStore for SlowTokenStore {
+    fn load(&self) -> Result<Option<Token>, Error> {
+        Ok(None)
+    }
+
+    fn save(&self, token: &Token) -> Result<(), Error> {
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        self.saved.lock().unwrap().push(token.as_string()?);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn saving_tokens_does_not_block_requests() {
+    let store = SlowTokenStore::default();
+    let saved = store.saved.clone();
+    let api = HomeAssistantAPI::builder(
+        "http://127.0.0.1:9".to_string(),
+        "http://client.example/".to_string(),
+    )
+    .token_store(Box::new(store))
+    .build()
+    .unwrap();
+
+    let started = std::time::Instant::now();
+    let setter = api.clone();
+    let saving = tokio::spawn(async move {
+        setter
+            .set_long_lived_token("long-lived".to_string())
+            .await
+            .unwrap()
+    });
+    tokio::time::delay_for(std::time::Duration::from_millis(50)).await;
+
+    // the save is still running, but neither the runtime nor the token wait for it
+    let token = api.token().await;
+    assert!(started.elapsed() < std::time::Duration::from_millis(250));
+    assert_eq!(token.as_string().unwrap(), "long-lived");
+    saving.await.unwrap();
+    assert_eq!(*saved.lock().unwrap(), vec!["long-lived"]);
+}
+
+#[tokio::test]
+async fn revoke_forgets_the_token() {
+    let revoked = Arc::new(Mutex::new(Vec::new()));
+    let recorded = revoked.clone();
+    let url = serve(move |parts, body| {
+        assert_eq!(parts.uri, "/auth/revoke");
+        recorded.lock().unwrap().push(body);
+        (StatusCode::OK, String::new())
+    })
+    .await;
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("access".to_string(), 1800, "stored-refresh".to_string())
+        .await
+        .unwrap();
+
+    api.revoke_token().await.unwrap();
+
+    assert_eq!(*revoked.lock().unwrap(), vec!["token=stored-refresh"]);
+    assert!(matches!(api.token().await, Token::None));
+}
+
+#[tokio::test]
+async fn revoke_falls_back_to_the_token_endpoint() {
+    let revoked = Arc::new(Mutex::new(Vec::new()));
+    let recorded = revoked.clone();
+    let url = serve(move |parts, body| {
+        recorded
+            .lock()
+            .unwrap()
+            .push((parts.uri.path().to_string(), body));
+        match parts.uri.path() {
+            "/auth/revoke" => (StatusCode::NOT_FOUND, String::new()),
+            _ => (StatusCode::OK, String::new()),
+        }
+    })
+    .await;
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("access".to_string(), 1800, "stored-refresh".to_string())
+        .await
+        .unwrap();
+
+    api.revoke_token().await.unwrap();
+
+    assert_eq!(
+        *revoked.lock().unwrap(),
+        vec![
+            (
+                "/auth/revoke".to_string(),
+                "token=stored-refresh".to_string()
+            ),
+            (
+                "/auth/token".to_string(),
+                "token=stored-refresh&action=revoke".to_string()
+            ),
+        ]
+    );
+    assert!(matches!(api.token().await, Token::None));
+}
+
+#[tokio::test]
+async fn failed_revoke_keeps_the_token() {
+    let url = serve(|parts, _| match parts.uri.path() {
+        "/auth/revoke" => (StatusCode::NOT_FOUND, String::new()),
+        _ => (StatusCode::BAD_REQUEST, String::new()),
+    })
+    .await;
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("access".to_string(), 1800, "stored-refresh".to_string())
+        .await
+        .unwrap();
+
+    match api.revoke_token().await {
+        Err(Error::BadRequest(_)) => {}
+        other => panic!("expected a bad request, got {:?}", other),
+    }
+    assert_eq!(api.token().await.as_string().unwrap(), "access");
+}
+
+#[tokio::test]
+async fn refresh_tokens_are_listed_and_deleted() {
+    let url = serve_websocket(|command| match command["type"].as_str().unwrap() {
+        "auth/refresh_tokens" => json!([{
+            "id": "token-1",
+            "client_id": "http://client.example/",
+            "client_name": null,
+            "client_icon": null,
+            "type": "normal",
+            "created_at": "2024-01-01T12:00:00+00:00",
+            "is_current": true,
+            "last_used_at": "2024-01-02T12:00:00+00:00",
+            "last_used_ip": "127.0.0.1",
+            "auth_provider_type": "homeassistant",
+        }]),
+        "auth/delete_refresh_token" => {
+            assert_eq!(command["refresh_token_id"], "token-1");
+            json!(null)
+        }
+        other => panic!("unexpected command {}", other),
+    })
+    .await;
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("access".to_string(), 1800, "stored-refresh".to_string())
+        .await
+        .unwrap();
+
+    let tokens = api.refresh_tokens().await.unwrap();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(tokens[0].id, "token-1");
+    assert!(tokens[0].is_current);
+    assert_eq!(tokens[0].last_used_ip.as_deref(), Some("127.0.0.1"));
+    api.delete_refresh_token(&tokens[0].id).await.unwrap();
+}
+
+#[tokio::test]
+async fn creates_long_lived_token() {
+    let url = serve_websocket(|command| {
+        assert_eq!(command["type"], "auth/long_lived_access_token");
+        assert_eq!(command["client_name"], "service account");
+        assert_eq!(command["lifespan"], 365);
+        json!("minted-long-lived")
+    })
+    .await;
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token("access".to_string(), 1800, "stored-refresh".to_string())
+        .await
+        .unwrap();
+
+    let token = api
+        .create_long_lived_token("service account", 365)
+        .await
+        .unwrap();
+
+    assert!(matches!(token, Token::LongLived(_)));
+    assert_eq!(token.as_string().unwrap(), "minted-long-lived");
+    assert_eq!(api.token().await.as_string().unwrap(), "access");
+}
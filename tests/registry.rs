@@ -0,0 +1,238 @@
+mod common;
+
+use common::{api, serve_websocket, serve_websocket_messages};
+use futures::StreamExt;
+use homeassistant::errors::Error;
+use homeassistant::registry::Registry;
+use homeassistant::types::{
+    EntityRegistryEntry, EntityRegistryUpdate, FloorRegistryUpdate, RegistryAction, StateObject,
+};
+use serde_json::json;
+
+async fn registry(url: String) -> Registry {
+    api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap()
+        .registry()
+}
+
+fn entity(entity_id: &str, area_id: Option<&str>, device_id: &str) -> serde_json::Value {
+    json!({
+        "entity_id": entity_id,
+        "id": format!("id-{}", entity_id),
+        "platform": "hue",
+        "unique_id": "00:17:88",
+        "name": null,
+        "original_name": "Ceiling",
+        "icon": null,
+        "area_id": area_id,
+        "device_id": device_id,
+        "config_entry_id": "entry",
+        "disabled_by": null,
+        "hidden_by": null,
+        "entity_category": null,
+        "has_entity_name": true,
+        "translation_key": null,
+        "labels": [],
+        "categories": {},
+        "options": {},
+    })
+}
+
+#[tokio::test]
+async fn entities_are_resolved_to_their_areas() {
+    let url = serve_websocket(|command| match command["type"].as_str().unwrap() {
+        "config/entity_registry/list" => json!([
+            entity("light.kitchen", None, "hue-1"),
+            entity("light.desk", Some("office"), "hue-1"),
+        ]),
+        "config/device_registry/list" => json!([{
+            "id": "hue-1",
+            "name": "Hue bulb",
+            "name_by_user": "Kitchen bulb",
+            "area_id": "kitchen",
+            "manufacturer": "Signify",
+            "model": "LCT015",
+            "config_entries": ["entry"],
+            "connections": [["zigbee", "00:17:88"]],
+            "identifiers": [["hue", "abc"]],
+            "disabled_by": null,
+            "via_device_id": null,
+            "labels": [],
+        }]),
+        "config/area_registry/list" => json!([{
+            "area_id": "kitchen",
+            "name": "Kitchen",
+            "aliases": [],
+            "floor_id": "ground",
+            "icon": null,
+            "picture": null,
+            "labels": [],
+        }]),
+        other => panic!("unexpected command {}", other),
+    })
+    .await;
+    let registry = registry(url).await;
+
+    let entities = registry.entities().await.unwrap();
+    let devices = registry.devices().await.unwrap();
+    assert_eq!(entities[0].display_name(), Some("Ceiling"));
+    assert_eq!(entities[0].area_id(&devices), Some("kitchen"));
+    assert_eq!(entities[1].area_id(&devices), Some("office"));
+    assert_eq!(devices[0].display_name(), Some("Kitchen bulb"));
+    assert_eq!(devices[0].identifiers, vec![("hue".into(), "abc".into())]);
+    assert_eq!(devices[0].entities(&entities).count(), 2);
+
+    let states: Vec<StateObject> = serde_json::from_value(json!([{
+        "entity_id": "light.kitchen",
+        "state": "on",
+        "attributes": {},
+        "last_changed": "2024-01-01T12:00:00+00:00",
+        "last_updated": "2024-01-01T12:00:00+00:00",
+    }]))
+    .unwrap();
+    assert_eq!(entities[0].state(&states).unwrap().state, "on");
+    assert!(entities[1].state(&states).is_none());
+
+    let area = registry.get_area("kitchen").await.unwrap();
+    assert_eq!(area.floor_id.as_deref(), Some("ground"));
+    match registry.get_device("missing").await {
+        Err(Error::NotFound(_)) => {}
+        other => panic!("expected not found, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn updates_only_send_the_changed_fields() {
+    let url = serve_websocket(|command| {
+        assert_eq!(command["type"], "config/entity_registry/update");
+        assert_eq!(command["entity_id"], "light.kitchen");
+        assert_eq!(command["name"], "Pendant");
+        assert!(command["area_id"].is_null());
+        assert!(command.get("area_id").is_some());
+        assert!(command.get("icon").is_none());
+        let mut entry = entity("light.kitchen", None, "hue-1");
+        entry["name"] = "Pendant".into();
+        json!({ "entity_entry": entry, "require_restart": false })
+    })
+    .await;
+    let registry = registry(url).await;
+
+    let update = EntityRegistryUpdate {
+        name: Some(Some("Pendant".to_string())),
+        area_id: Some(None),
+        ..EntityRegistryUpdate::default()
+    };
+    let entry = registry
+        .update_entity("light.kitchen", &update)
+        .await
+        .unwrap();
+    assert_eq!(entry.display_name(), Some("Pendant"));
+}
+
+#[tokio::test]
+async fn registry_updates_are_typed() {
+    let url = serve_websocket_messages(|command| {
+        assert_eq!(command["type"], "subscribe_events");
+        assert_eq!(command["event_type"], "entity_registry_updated");
+        vec![
+            json!({"id": command["id"], "type": "result", "success": true, "result": null}),
+            json!({
+                "id": command["id"],
+                "type": "event",
+                "event": {
+                    "event_type": "entity_registry_updated",
+                    "data": {
+                        "action": "update",
+                        "entity_id": "light.pendant",
+                        "old_entity_id": "light.kitchen",
+                        "changes": {"entity_id": "light.kitchen"},
+                    },
+                    "origin": "LOCAL",
+                    "time_fired": "2024-01-01T12:00:00+00:00",
+                    "context": {"id": "01HKZ", "parent_id": null, "user_id": null},
+                },
+            }),
+        ]
+    })
+    .await;
+    let registry = registry(url).await;
+
+    let mut updates = registry.subscribe_entity_updates().await.unwrap();
+    let update = updates.next().await.unwrap().unwrap().data;
+    assert_eq!(update.action, RegistryAction::Update);
+    assert_eq!(update.entity_id, "light.pendant");
+    assert_eq!(update.old_entity_id.as_deref(), Some("light.kitchen"));
+}
+
+fn ids<'a>(entities: impl Iterator<Item = &'a EntityRegistryEntry>) -> Vec<&'a str> {
+    entities.map(|entity| entity.entity_id.as_str()).collect()
+}
+
+#[tokio::test]
+async fn entities_are_resolved_by_floor_and_label() {
+    let url = serve_websocket(|command| match command["type"].as_str().unwrap() {
+        "config/entity_registry/list" => {
+            let mut desk = entity("light.desk", Some("office"), "hue-2");
+            desk["labels"] = json!(["work"]);
+            json!([
+                entity("light.kitchen", None, "hue-1"),
+                desk,
+                entity("sensor.cellar", Some("cellar"), "sensor-1"),
+            ])
+        }
+        "config/device_registry/list" => json!([
+            {"id": "hue-1", "area_id": "kitchen", "labels": ["lights"]},
+            {"id": "hue-2", "area_id": "office", "labels": ["lights"]},
+            {"id": "sensor-1", "area_id": null, "labels": []},
+        ]),
+        "config/area_registry/list" => json!([
+            {"area_id": "kitchen", "name": "Kitchen", "floor_id": "ground", "labels": []},
+            {"area_id": "office", "name": "Office", "floor_id": "upstairs", "labels": []},
+            {"area_id": "cellar", "name": "Cellar", "floor_id": "ground", "labels": ["work"]},
+        ]),
+        "config/floor_registry/create" => {
+            assert_eq!(command["name"], "Attic");
+            assert_eq!(command["level"], 2);
+            assert!(command.get("icon").is_none());
+            json!({"floor_id": "attic", "name": "Attic", "aliases": [], "icon": null, "level": 2})
+        }
+        "config/label_registry/delete" => {
+            assert_eq!(command["label_id"], "work");
+            json!("success")
+        }
+        other => panic!("unexpected command {}", other),
+    })
+    .await;
+    let registry = registry(url).await;
+
+    let snapshot = registry.snapshot().await.unwrap();
+    assert_eq!(
+        ids(snapshot.entities_on_floor("ground")),
+        vec!["light.kitchen", "sensor.cellar"]
+    );
+    assert_eq!(
+        ids(snapshot.entities_with_label("work")),
+        vec!["light.desk", "sensor.cellar"]
+    );
+    assert_eq!(
+        ids(snapshot.entities_with_label("lights")),
+        vec!["light.kitchen", "light.desk"]
+    );
+    assert_eq!(snapshot.area_of("light.kitchen").unwrap().name, "Kitchen");
+
+    let floor = registry
+        .create_floor(
+            "Attic",
+            &FloorRegistryUpdate {
+                level: Some(Some(2)),
+                ..FloorRegistryUpdate::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(floor.level, Some(2));
+    registry.delete_label("work").await.unwrap();
+}
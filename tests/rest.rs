@@ -0,0 +1,362 @@
+mod common;
+
+use chrono::TimeZone;
+use common::{api, bearer, serve};
+use homeassistant::errors::Error;
+use homeassistant::types::{EntityId, ServiceResponse};
+use homeassistant::HomeAssistantAPI;
+use hyper::StatusCode;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn rejected_token_is_refreshed_and_retried_once() {
+    let refreshes = Arc::new(AtomicUsize::new(0));
+    let counter = refreshes.clone();
+    let url = serve(move |parts, _| match parts.uri.path() {
+        "/auth/token" => {
+            counter.fetch_add(1, Ordering::SeqCst);
+            (
+                StatusCode::OK,
+                r#"{"access_token":"fresh-access","expires_in":1800,"token_type":"Bearer"}"#
+                    .to_string(),
+            )
+        }
+        "/api/" if bearer(parts) == "fresh-access" => {
+            (StatusCode::OK, r#"{"message":"API running."}"#.to_string())
+        }
+        "/api/" | "/api/config" => (StatusCode::UNAUTHORIZED, "401: Unauthorized".to_string()),
+        other => panic!("unexpected request to {}", other),
+    })
+    .await;
+
+    let api = HomeAssistantAPI::new(url, "http://client.example/".to_string());
+    api.set_oauth_token(
+        "revoked-access".to_string(),
+        1800,
+        "stored-refresh".to_string(),
+    )
+    .await
+    .unwrap();
+    let rest = api.get_rest_client().await;
+
+    assert_eq!(rest.check().await.unwrap(), "API running.");
+    assert_eq!(refreshes.load(Ordering::SeqCst), 1);
+
+    match rest.config().await {
+        Err(Error::Unauthorized(_)) => {}
+        other => panic!("expected a 401, got {:?}", other),
+    }
+    assert_eq!(refreshes.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn error_status_carries_the_ha_message() {
+    let url = serve(|parts, _| match parts.uri.path() {
+        "/api/states/light.missing" => (
+            StatusCode::NOT_FOUND,
+            r#"{"message":"Entity not found."}"#.to_string(),
+        ),
+        "/api/config" => (StatusCode::SERVICE_UNAVAILABLE, "Starting".to_string()),
+        "/api/template" => (
+            StatusCode::BAD_REQUEST,
+            "Error rendering template".to_string(),
+        ),
+        other => panic!("unexpected request to {}", other),
+    })
+    .await;
+
+    let api = api(url, "long-lived").await;
+    let rest = api.get_rest_client().await;
+
+    match rest.state_of(&"light.missing".parse().unwrap()).await {
+        Err(Error::NotFound(message)) => assert_eq!(message, "Entity not found."),
+        other => panic!("expected a 404, got {:?}", other),
+    }
+    match rest.template_render("{{ broken".to_string()).await {
+        Err(Error::BadRequest(message)) => assert_eq!(message, "Error rendering template"),
+        other => panic!("expected a 400, got {:?}", other),
+    }
+    match rest.config().await {
+        Err(error) => assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE)),
+        other => panic!("expected a 503, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn decode_errors_keep_the_body() {
+    let url = serve(|_, _| (StatusCode::OK, r#"{"version":"2024.1.0"}"#.to_string())).await;
+
+    let api = api(url, "long-lived").await;
+    let rest = api.get_rest_client().await;
+
+    let error = rest.discovery_info().await.unwrap_err();
+    assert!(std::error::Error::source(&error).is_some());
+    match error {
+        Error::Decode(_, body) => assert_eq!(body, r#"{"version":"2024.1.0"}"#),
+        other => panic!("expected a decode error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn service_calls_can_return_a_response() {
+    let url = serve(|parts, body| {
+        assert_eq!(parts.uri.path(), "/api/services/weather/get_forecasts");
+        assert_eq!(body, r#"{"entity_id":"weather.home","type":"daily"}"#);
+        match parts.uri.query() {
+            Some(query) if query.starts_with("return_response") => (
+                StatusCode::OK,
+                r#"{"changed_states":[],"service_response":{"weather.home":{"forecast":[{"temperature":21.5}]}}}"#
+                    .to_string(),
+            ),
+            _ => (
+                StatusCode::BAD_REQUEST,
+                r#"{"message":"Service call requires responses but caller did not ask for responses"}"#
+                    .to_string(),
+            ),
+        }
+    })
+    .await;
+
+    let api = api(url, "long-lived").await;
+    let rest = api.get_rest_client().await;
+    let data = json!({"entity_id": "weather.home", "type": "daily"});
+
+    let response: ServiceResponse<HashMap<String, serde_json::Value>> = rest
+        .service_call_with_response(
+            "weather".to_string(),
+            "get_forecasts".to_string(),
+            Some(&data),
+        )
+        .await
+        .unwrap();
+    assert!(response.changed_states.is_empty());
+    assert_eq!(
+        response.service_response["weather.home"]["forecast"][0]["temperature"],
+        21.5
+    );
+
+    match rest
+        .service_call(
+            "weather".to_string(),
+            "get_forecasts".to_string(),
+            Some(&data),
+        )
+        .await
+    {
+        Err(Error::BadRequest(_)) => {}
+        other => panic!("expected a bad request, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn states_keep_typed_attributes_and_timestamps() {
+    let url = serve(|parts, _| {
+        assert_eq!(parts.uri.path(), "/api/states");
+        (
+            StatusCode::OK,
+            r#"[{
+                "entity_id": "light.kitchen",
+                "state": "on",
+                "attributes": {
+                    "friendly_name": "Kitchen",
+                    "brightness": 180,
+                    "rgb_color": [255, 120, 0],
+                    "effect_list": ["none", "colorloop"],
+                    "supported_features": 44
+                },
+                "last_changed": "2024-01-01T12:00:00.123456+00:00",
+                "last_updated": "2024-01-01T12:05:00+00:00",
+                "last_reported": "2024-01-01T12:10:00+00:00",
+                "context": {"id": "01HKZ", "parent_id": null, "user_id": "abc"}
+            }, {
+                "entity_id": "sensor.outside",
+                "state": "-3.5",
+                "attributes": {"unit_of_measurement": "°C", "device_class": "temperature"},
+                "last_changed": "2024-01-01T12:00:00+00:00",
+                "last_updated": "2024-01-01T12:00:00+00:00",
+                "context": {"id": "01HL0", "parent_id": null, "user_id": null}
+            }]"#
+            .to_string(),
+        )
+    })
+    .await;
+
+    let api = api(url, "long-lived").await;
+    let states = api.get_rest_client().await.states().await.unwrap();
+
+    let light = &states[0];
+    assert_eq!(light.domain(), "light");
+    assert_eq!(light.friendly_name(), Some("Kitchen"));
+    assert_eq!(light.attribute::<u8>("brightness"), Some(180));
+    assert_eq!(light.attribute::<[u8; 3]>("rgb_color"), Some([255, 120, 0]));
+    assert_eq!(light.attribute::<String>("brightness"), None);
+    assert_eq!(light.supported_features(), 44);
+    assert_eq!(light.last_changed.timestamp_subsec_micros(), 123_456);
+    assert!(light.last_updated < light.last_reported.unwrap());
+    assert_eq!(
+        light.context.as_ref().unwrap().user_id.as_deref(),
+        Some("abc")
+    );
+
+    let sensor = &states[1];
+    assert_eq!(sensor.numeric_state(), Some(-3.5));
+    assert_eq!(sensor.unit_of_measurement(), Some("°C"));
+    assert_eq!(sensor.device_class(), Some("temperature"));
+    assert!(sensor.last_reported.is_none());
+    assert!(!sensor.is_unavailable());
+}
+
+#[test]
+fn entity_ids_are_validated() {
+    let entity_id: EntityId = "binary_sensor.front_door_2".parse().unwrap();
+    assert_eq!(entity_id.domain(), "binary_sensor");
+    assert_eq!(entity_id.object_id(), "front_door_2");
+    assert_eq!(entity_id.path_segment(), "binary_sensor.front_door_2");
+    assert_eq!(json!(entity_id), json!("binary_sensor.front_door_2"));
+
+    for invalid in [
+        "light",
+        "light.",
+        ".kitchen",
+        "Light.kitchen",
+        "light.kitchen.ceiling",
+        "light._kitchen",
+        "light.kitchen_",
+        "light.kitchen__ceiling",
+        "light.kitchen/../../config",
+        "light.küche",
+    ]
+    .iter()
+    {
+        match invalid.parse::<EntityId>() {
+            Err(Error::InvalidEntityId(entity_id)) => assert_eq!(&entity_id, invalid),
+            other => panic!("expected {} to be rejected, got {:?}", invalid, other),
+        }
+    }
+    assert!(serde_json::from_value::<EntityId>(json!("light.kitchen lamp")).is_err());
+}
+
+#[tokio::test]
+async fn entity_ids_address_single_states() {
+    let url = serve(|parts, body| {
+        assert_eq!(parts.uri.path(), "/api/states/sensor.outside");
+        if parts.method == hyper::Method::POST {
+            assert_eq!(body, r#"{"state":"-2"}"#);
+        }
+        (
+            StatusCode::OK,
+            r#"{
+                "entity_id": "sensor.outside",
+                "state": "-2",
+                "attributes": {},
+                "last_changed": "2024-01-01T12:00:00+00:00",
+                "last_updated": "2024-01-01T12:00:00+00:00"
+            }"#
+            .to_string(),
+        )
+    })
+    .await;
+
+    let api = api(url, "long-lived").await;
+    let rest = api.get_rest_client().await;
+    let entity_id: EntityId = "sensor.outside".parse().unwrap();
+
+    let state = rest.state_of(&entity_id).await.unwrap();
+    assert_eq!(entity_id, state.entity_id.as_str());
+    let state = rest
+        .state_change(&entity_id, Some(json!({"state": "-2"})))
+        .await
+        .unwrap();
+    assert_eq!(state.numeric_state(), Some(-2.0));
+}
+
+#[tokio::test]
+async fn history_is_grouped_by_entity() {
+    let url = serve(|parts, _| match parts.uri.path() {
+        "/api/history/period/2024-01-01T00:00:00+00:00" => {
+            let query = parts.uri.query().unwrap();
+            assert!(query.contains("filter_entity_id=sensor.outside%2Clight.kitchen"));
+            assert!(query.contains("end_time=2024-01-02T00%3A00%3A00%2B00%3A00"));
+            assert!(query.contains("minimal_response"));
+            assert!(query.contains("significant_changes_only=0"));
+            assert!(!query.contains("no_attributes"));
+            (
+                StatusCode::OK,
+                r#"[[
+                    {"entity_id": "sensor.outside", "state": "-3", "attributes": {"unit_of_measurement": "°C"},
+                     "last_changed": "2024-01-01T00:00:00+00:00", "last_updated": "2024-01-01T00:00:00+00:00"},
+                    {"state": "-2.5", "last_changed": "2024-01-01T01:00:00+00:00"},
+                    {"state": "-1", "last_changed": "2024-01-01T02:00:00+00:00"}
+                ], [
+                    {"entity_id": "light.kitchen", "state": "off", "attributes": {},
+                     "last_changed": "2023-12-31T22:00:00+00:00", "last_updated": "2024-01-01T00:00:00+00:00"},
+                    {"state": "on", "last_changed": "2024-01-01T07:00:00+00:00"}
+                ]]"#
+                .to_string(),
+            )
+        }
+        "/api/logbook/2024-01-01T00:00:00+00:00" => {
+            assert_eq!(parts.uri.query(), Some("entity=light.kitchen"));
+            (StatusCode::OK, "[]".to_string())
+        }
+        other => panic!("unexpected request to {}", other),
+    })
+    .await;
+
+    let api = api(url, "long-lived").await;
+    let rest = api.get_rest_client().await;
+    let start = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let kitchen: EntityId = "light.kitchen".parse().unwrap();
+
+    let history = rest
+        .history()
+        .entity_ids(vec!["sensor.outside".parse().unwrap(), kitchen.clone()])
+        .start(start)
+        .end(start + chrono::Duration::days(1))
+        .minimal_response()
+        .significant_changes_only(false)
+        .send()
+        .await
+        .unwrap();
+
+    let outside = &history["sensor.outside"];
+    assert_eq!(outside.len(), 3);
+    assert_eq!(
+        outside[0].attributes.as_ref().unwrap()["unit_of_measurement"],
+        "°C"
+    );
+    assert_eq!(outside[2].state, "-1");
+    assert!(outside[2].attributes.is_none());
+    assert_eq!(outside[2].last_updated, outside[2].last_changed);
+    let light = &history["light.kitchen"];
+    assert_eq!(light[0].last_updated, start);
+    assert_eq!(light[1].state, "on");
+
+    #[allow(deprecated)]
+    let flattened = rest
+        .history_period_minimal(
+            Some(start),
+            Some("sensor.outside,light.kitchen".to_string()),
+            Some(start + chrono::Duration::days(1)),
+            Some(false),
+        )
+        .await
+        .unwrap();
+    assert_eq!(flattened.len(), 5);
+    assert_eq!(flattened[0].entity_id, "light.kitchen");
+    assert_eq!(flattened[4].state, "-1");
+
+    match rest.history().start(start).send().await {
+        Err(Error::Config(_)) => {}
+        other => panic!("expected a missing entity id, got {:?}", other),
+    }
+
+    assert!(rest
+        .logbook(Some(start), &kitchen, None)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use common::{api, serve};
+use homeassistant::errors::Error;
+use homeassistant::types::Selector;
+use hyper::StatusCode;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const SERVICES: &str = r#"[{
+    "domain": "light",
+    "services": {
+        "turn_on": {
+            "name": "Turn on",
+            "description": "Turns on one or more lights.",
+            "fields": {
+                "transition": {
+                    "filter": {"supported_features": [32]},
+                    "selector": {"number": {"min": 0, "max": 300, "unit_of_measurement": "seconds"}}
+                },
+                "advanced_fields": {
+                    "collapsed": true,
+                    "fields": {
+                        "profile": {"example": "relax", "selector": {"text": null}},
+                        "flash": {"selector": {"select": {"options": ["long", "short"]}}}
+                    }
+                },
+                "brightness_pct": {
+                    "name": "Brightness",
+                    "selector": {"number": {"min": 0, "max": 100, "step": 1}}
+                },
+                "effect": {"selector": {"color_rgb": {}}}
+            },
+            "target": {"entity": [{"domain": ["light"]}]}
+        }
+    }
+}, {
+    "domain": "notify",
+    "services": {
+        "send_message": {
+            "fields": {
+                "message": {"required": true, "selector": {"text": {"multiline": true}}},
+                "entity_id": {"selector": {"entity": {"filter": {"domain": "notify"}, "multiple": true}}}
+            },
+            "response": {"optional": true}
+        }
+    }
+}]"#;
+
+#[tokio::test]
+async fn service_data_is_validated_before_calling() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let url = serve(move |parts, body| match parts.uri.path() {
+        "/api/services" => (StatusCode::OK, SERVICES.to_string()),
+        "/api/services/light/turn_on" => {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert_eq!(body, r#"{"brightness_pct":50,"entity_id":"light.kitchen"}"#);
+            (StatusCode::OK, "[]".to_string())
+        }
+        other => panic!("unexpected request to {}", other),
+    })
+    .await;
+    let api = api(url, "long-lived").await;
+    let rest = api.get_rest_client().await;
+
+    let catalog = rest.service_catalog().await.unwrap();
+    let turn_on = catalog.get("light", "turn_on").unwrap();
+    assert_eq!(turn_on.name.as_deref(), Some("Turn on"));
+    assert_eq!(turn_on.all_fields().len(), 5);
+    match &turn_on.fields["brightness_pct"].selector {
+        Some(Selector::Number(number)) => assert_eq!(number.max, Some(100.0)),
+        other => panic!("expected a number selector, got {:?}", other),
+    }
+    match &turn_on.fields["effect"].selector {
+        Some(Selector::Other(kind, _)) => assert_eq!(kind, "color_rgb"),
+        other => panic!("expected an other selector, got {:?}", other),
+    }
+    assert_eq!(turn_on.target.as_ref().unwrap().entity[0].domain, ["light"]);
+    assert!(
+        catalog
+            .get("notify", "send_message")
+            .unwrap()
+            .response
+            .as_ref()
+            .unwrap()
+            .optional
+    );
+
+    rest.validated_service_call(
+        &catalog,
+        "light".to_string(),
+        "turn_on".to_string(),
+        Some(json!({"brightness_pct": 50, "entity_id": "light.kitchen"})),
+    )
+    .await
+    .unwrap();
+
+    let rejected = [
+        json!({"brightness_pct": 150}),
+        json!({"brightness_pct": "half"}),
+        json!({"flash": "medium"}),
+        json!({"colour": "red"}),
+        json!({"entity_id": 5}),
+    ];
+    for data in rejected.iter() {
+        match rest
+            .validated_service_call(
+                &catalog,
+                "light".to_string(),
+                "turn_on".to_string(),
+                Some(data),
+            )
+            .await
+        {
+            Err(Error::InvalidServiceData(_)) => {}
+            other => panic!("expected {} to be rejected, got {:?}", data, other),
+        }
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    assert!(catalog
+        .validate("notify", "send_message", &json!({"title": "Hi"}))
+        .is_err());
+    assert!(catalog
+        .validate("notify", "send_message", &json!({"message": "Hi"}))
+        .is_ok());
+    assert!(catalog
+        .validate(
+            "notify",
+            "send_message",
+            &json!({"message": "Hi", "entity_id": ["notify.phone", "light.kitchen"]}),
+        )
+        .is_err());
+    assert!(catalog.validate("light", "toggle", &json!({})).is_err());
+}
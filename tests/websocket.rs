@@ -0,0 +1,843 @@
+mod common;
+
+use chrono::TimeZone;
+use common::{api, serve_websocket, serve_websocket_messages};
+use futures::{SinkExt, StreamExt};
+use homeassistant::errors::Error;
+use homeassistant::mirror::StateWatch;
+use homeassistant::trigger::Trigger;
+use homeassistant::types::{
+    ServiceTarget, StateObject, StatisticIdType, StatisticType, StatisticsPeriod, TemplateUpdate,
+};
+use homeassistant::websocket::ConnectionState;
+use homeassistant::HomeAssistantAPI;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+#[tokio::test(threaded_scheduler)]
+async fn commands_are_matched_to_their_results() {
+    let url =
+        serve_websocket(|command| json!({ "echo": command["type"], "id": command["id"] })).await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+    assert_eq!(websocket.ha_version(), "2024.1.0");
+
+    let commands: Vec<_> = (0..8)
+        .map(|i| {
+            let websocket = websocket.clone();
+            tokio::spawn(async move {
+                let result: HashMap<String, serde_json::Value> = websocket
+                    .command(json!({ "type": format!("command/{}", i) }))
+                    .await
+                    .unwrap();
+                (i, result)
+            })
+        })
+        .collect();
+
+    let mut ids = Vec::new();
+    for command in commands {
+        let (i, result) = command.await.unwrap();
+        assert_eq!(result["echo"], format!("command/{}", i));
+        ids.push(result["id"].as_u64().unwrap());
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 8);
+
+    websocket.ping().await.unwrap();
+}
+
+#[tokio::test]
+async fn failed_commands_carry_the_ha_error() {
+    let url = serve_websocket_messages(|command| {
+        vec![
+            json!({"type": "event", "id": 999, "event": {}}),
+            json!({
+                "id": command["id"],
+                "type": "result",
+                "success": false,
+                "error": {"code": "unknown_command", "message": "Unknown command."},
+            }),
+        ]
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    match websocket.command::<()>(json!({"type": "nope"})).await {
+        Err(Error::Command(code, message)) => {
+            assert_eq!(code, "unknown_command");
+            assert_eq!(message, "Unknown command.");
+        }
+        other => panic!("expected a command error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn undecodable_results_fail_their_command() {
+    let url = serve_websocket_messages(|command| {
+        vec![json!({
+            "id": command["id"],
+            "type": "result",
+            "success": false,
+            "error": "not an error object",
+        })]
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        websocket.command::<()>(json!({"type": "nope"})),
+    )
+    .await
+    .expect("the command was never resolved");
+    match result {
+        Err(Error::Decode(_, body)) => assert!(body.contains("not an error object")),
+        other => panic!("expected a decode error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn abandoned_subscriptions_are_unsubscribed() {
+    let subscribe_id = Arc::new(Mutex::new(None));
+    let unsubscribed = Arc::new(Mutex::new(Vec::new()));
+    let recorded = unsubscribed.clone();
+    let url = serve_websocket_messages(move |command| {
+        let result = |id: &serde_json::Value| {
+            json!({"id": id, "type": "result", "success": true, "result": null})
+        };
+        match command["type"].as_str().unwrap() {
+            // only confirmed along with the next ping, after the caller gave up
+            "subscribe_events" => {
+                *subscribe_id.lock().unwrap() = Some(command["id"].clone());
+                vec![]
+            }
+            "ping" => {
+                let mut replies: Vec<_> = subscribe_id.lock().unwrap().take().iter().map(result).collect();
+                replies.push(pong(command));
+                replies
+            }
+            "unsubscribe_events" => {
+                recorded.lock().unwrap().push(command["subscription"].clone());
+                vec![result(&command["id"])]
+            }
+            other => panic!("unexpected command {}", other),
+        }
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    let subscribe = websocket.subscribe_events(Some("state_changed"));
+    assert!(tokio::time::timeout(Duration::from_millis(50), subscribe)
+        .await
+        .is_err());
+    websocket.ping().await.unwrap();
+    // the unsubscribe went out before this ping
+    websocket.ping().await.unwrap();
+
+    assert_eq!(*unsubscribed.lock().unwrap(), vec![json!(1)]);
+}
+
+#[tokio::test]
+async fn rejected_tokens_fail_the_handshake() {
+    let url = serve_websocket(|_| json!(null)).await;
+
+    match api(url, "wrong").await.get_websocket_client().await {
+        Err(Error::Unauthorized(message)) => assert_eq!(message, "Invalid access token"),
+        other => panic!("expected the handshake to fail, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn silent_servers_time_out_the_handshake() {
+    // accepts connections but never answers the websocket upgrade
+    let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut idle = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            idle.push(stream);
+        }
+    });
+
+    let api = HomeAssistantAPI::builder(url, "http://client.example/".to_string())
+        .timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    api.set_long_lived_token("access".to_string())
+        .await
+        .unwrap();
+
+    match api.get_websocket_client().await {
+        Err(Error::Timeout(_)) => {}
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn connect_timeouts_leave_slow_authentication_alone() {
+    // connects at once but takes a while to ask for the token
+    let mut listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        tokio::time::delay_for(Duration::from_millis(300)).await;
+        let send = |value: serde_json::Value| Message::Text(value.to_string());
+        socket
+            .send(send(json!({"type": "auth_required"})))
+            .await
+            .unwrap();
+        common::next_json(&mut socket).await.unwrap();
+        socket
+            .send(send(json!({"type": "auth_ok", "ha_version": "2024.1.0"})))
+            .await
+            .unwrap();
+        while common::next_json(&mut socket).await.is_some() {}
+    });
+
+    let api = HomeAssistantAPI::builder(url, "http://client.example/".to_string())
+        .connect_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    api.set_long_lived_token("access".to_string())
+        .await
+        .unwrap();
+
+    let websocket = api.get_websocket_client().await.unwrap();
+    assert_eq!(websocket.ha_version(), "2024.1.0");
+}
+
+fn event(id: &serde_json::Value, event_type: &str, entity_id: &str) -> serde_json::Value {
+    json!({
+        "id": id,
+        "type": "event",
+        "event": {
+            "event_type": event_type,
+            "data": {"entity_id": entity_id},
+            "origin": "LOCAL",
+            "time_fired": "2024-01-01T12:00:00.123456+00:00",
+            "context": {"id": "01HKZ", "parent_id": null, "user_id": null},
+        },
+    })
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn subscribed_events_are_streamed_until_dropped() {
+    let commands = Arc::new(Mutex::new(Vec::new()));
+    let received = commands.clone();
+    let url = serve_websocket_messages(move |command| {
+        received.lock().unwrap().push(command.clone());
+        let result =
+            json!({"id": command["id"], "type": "result", "success": true, "result": null});
+        if command["type"] == "subscribe_events" {
+            vec![
+                result,
+                event(&command["id"], "state_changed", "light.kitchen"),
+                event(&command["id"], "state_changed", "light.hallway"),
+            ]
+        } else {
+            vec![result]
+        }
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    let mut events = websocket
+        .subscribe_events(Some("state_changed"))
+        .await
+        .unwrap();
+    let first = events.next().await.unwrap().unwrap();
+    assert_eq!(first.event_type, "state_changed");
+    assert_eq!(first.data["entity_id"], "light.kitchen");
+    assert_eq!(first.origin, "LOCAL");
+    assert_eq!(first.context.id, "01HKZ");
+    assert_eq!(first.time_fired.timestamp(), 1_704_110_400);
+    let second = events.next().await.unwrap().unwrap();
+    assert_eq!(second.data["entity_id"], "light.hallway");
+
+    let subscription_id = commands.lock().unwrap()[0]["id"].clone();
+    assert_eq!(commands.lock().unwrap()[0]["event_type"], "state_changed");
+    drop(events);
+    // the unsubscribe is sent before this ping, so it has been handled once the pong arrives
+    websocket.ping().await.unwrap();
+
+    let commands = commands.lock().unwrap();
+    assert_eq!(commands[1]["type"], "unsubscribe_events");
+    assert_eq!(commands[1]["subscription"], subscription_id);
+}
+
+async fn reconnecting_api(url: String) -> HomeAssistantAPI {
+    let api = HomeAssistantAPI::builder(url, "http://client.example/".to_string())
+        .websocket_ping_interval(Duration::from_millis(50))
+        .websocket_reconnect_delay(Duration::from_millis(10), Duration::from_millis(100))
+        .build()
+        .unwrap();
+    api.set_long_lived_token("access".to_string())
+        .await
+        .unwrap();
+    api
+}
+
+fn pong(command: &serde_json::Value) -> serde_json::Value {
+    json!({"id": command["id"], "type": "pong"})
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn subscriptions_survive_reconnects() {
+    let subscribes = Arc::new(Mutex::new(Vec::new()));
+    let received = subscribes.clone();
+    let url = serve_websocket_messages(move |command| {
+        if command["type"] == "ping" {
+            return vec![pong(command)];
+        }
+        let mut subscribes = received.lock().unwrap();
+        subscribes.push(command.clone());
+        let result =
+            json!({"id": command["id"], "type": "result", "success": true, "result": null});
+        match subscribes.len() {
+            // HA restarts right after the first event
+            1 => vec![
+                result,
+                event(&command["id"], "state_changed", "light.kitchen"),
+                json!(null),
+            ],
+            _ => vec![
+                result,
+                event(&command["id"], "state_changed", "light.hallway"),
+            ],
+        }
+    })
+    .await;
+    let websocket = reconnecting_api(url)
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+    let mut states = websocket.connection_states();
+
+    let mut events = websocket
+        .subscribe_events(Some("state_changed"))
+        .await
+        .unwrap();
+    let first = events.next().await.unwrap().unwrap();
+    assert_eq!(first.data["entity_id"], "light.kitchen");
+    let second = events.next().await.unwrap().unwrap();
+    assert_eq!(second.data["entity_id"], "light.hallway");
+
+    assert_eq!(states.next().await, Some(ConnectionState::Disconnected));
+    assert_eq!(states.next().await, Some(ConnectionState::Reconnecting(1)));
+    assert_eq!(states.next().await, Some(ConnectionState::Connected));
+
+    let subscribes = subscribes.lock().unwrap();
+    assert_eq!(subscribes[1]["type"], "subscribe_events");
+    assert_eq!(subscribes[1]["event_type"], "state_changed");
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn unanswered_pings_trigger_a_reconnect() {
+    let pings = Arc::new(AtomicUsize::new(0));
+    let counter = pings.clone();
+    let url = serve_websocket_messages(move |command| {
+        match command["type"].as_str() {
+            // the first connection stops answering without closing
+            Some("ping") if counter.fetch_add(1, Ordering::SeqCst) == 0 => vec![],
+            Some("ping") => vec![pong(command)],
+            _ => {
+                vec![json!({"id": command["id"], "type": "result", "success": true, "result": 42})]
+            }
+        }
+    })
+    .await;
+    let websocket = reconnecting_api(url)
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+    let mut states = websocket.connection_states();
+
+    assert_eq!(states.next().await, Some(ConnectionState::Disconnected));
+    assert_eq!(states.next().await, Some(ConnectionState::Reconnecting(1)));
+    assert_eq!(states.next().await, Some(ConnectionState::Connected));
+    let answer: u32 = websocket.command(json!({"type": "answer"})).await.unwrap();
+    assert_eq!(answer, 42);
+}
+
+#[tokio::test]
+async fn triggers_are_serialized_and_their_variables_streamed() {
+    let url = serve_websocket_messages(|command| {
+        assert_eq!(command["type"], "subscribe_trigger");
+        assert_eq!(
+            command["trigger"],
+            json!({
+                "platform": "state",
+                "entity_id": ["binary_sensor.front_door"],
+                "to": "on",
+                "for": 30.0,
+            })
+        );
+        assert_eq!(command["variables"], json!({"room": "hall"}));
+        vec![
+            json!({"id": command["id"], "type": "result", "success": true, "result": null}),
+            json!({
+                "id": command["id"],
+                "type": "event",
+                "event": {
+                    "variables": {
+                        "room": "hall",
+                        "trigger": {
+                            "id": "0",
+                            "idx": "0",
+                            "alias": null,
+                            "platform": "state",
+                            "entity_id": "binary_sensor.front_door",
+                            "from_state": null,
+                            "to_state": {
+                                "entity_id": "binary_sensor.front_door",
+                                "state": "on",
+                                "attributes": {"device_class": "door", "battery": 87},
+                                "last_changed": "2024-01-01T12:00:00+00:00",
+                                "last_updated": "2024-01-01T12:00:00+00:00",
+                                "context": {"id": "01HKZ", "parent_id": null, "user_id": null},
+                            },
+                            "for": {"seconds": 30},
+                            "attribute": null,
+                            "description": "state of binary_sensor.front_door",
+                        },
+                    },
+                    "context": {"id": "01HL0", "parent_id": "01HKZ", "user_id": null},
+                },
+            }),
+        ]
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    let trigger = Trigger::state(vec!["binary_sensor.front_door"])
+        .to("on")
+        .for_duration(Duration::from_secs(30));
+    let mut fired = websocket
+        .subscribe_trigger(trigger, Some(json!({"room": "hall"})))
+        .await
+        .unwrap();
+
+    let event = fired.next().await.unwrap().unwrap();
+    let trigger = event.variables.trigger;
+    assert_eq!(trigger.platform, "state");
+    assert_eq!(
+        trigger.entity_id.as_deref(),
+        Some("binary_sensor.front_door")
+    );
+    let to_state = trigger.to_state.unwrap();
+    assert_eq!(to_state.state, "on");
+    assert_eq!(to_state.attributes["battery"], 87);
+    assert_eq!(to_state.context.unwrap().id, "01HKZ");
+    assert_eq!(event.variables.variables["room"], "hall");
+    assert_eq!(event.context.unwrap().parent_id.as_deref(), Some("01HKZ"));
+}
+
+#[test]
+fn triggers_serialize_to_the_automation_schema() {
+    let triggers: Vec<Trigger> = vec![
+        Trigger::numeric_state(vec!["sensor.temperature"])
+            .above(25.5)
+            .id("hot")
+            .into(),
+        Trigger::time_pattern().minutes("/5").into(),
+        Trigger::template("{{ is_state('sun.sun', 'below_horizon') }}").into(),
+        Trigger::event("call_service")
+            .event_data(json!({"domain": "light"}))
+            .into(),
+        Trigger::home_assistant_start().into(),
+    ];
+
+    assert_eq!(
+        serde_json::to_value(&triggers).unwrap(),
+        json!([
+            {"platform": "numeric_state", "entity_id": ["sensor.temperature"], "above": 25.5, "id": "hot"},
+            {"platform": "time_pattern", "minutes": "/5"},
+            {"platform": "template", "value_template": "{{ is_state('sun.sun', 'below_horizon') }}"},
+            {"platform": "event", "event_type": "call_service", "event_data": {"domain": "light"}},
+            {"platform": "homeassistant", "event": "start"},
+        ])
+    );
+}
+
+async fn wait_for<F>(receiver: &mut StateWatch, done: F) -> Option<StateObject>
+where
+    F: Fn(&Option<StateObject>) -> bool,
+{
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let state = receiver.recv().await.unwrap();
+            if done(&state) {
+                return state;
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn state_mirror_applies_compressed_diffs() {
+    let subscription = Arc::new(Mutex::new(json!(null)));
+    let subscription_id = subscription.clone();
+    let unsubscribed = Arc::new(Mutex::new(Vec::new()));
+    let recorded = unsubscribed.clone();
+    let url = serve_websocket_messages(move |command| {
+        let result =
+            json!({"id": command["id"], "type": "result", "success": true, "result": null});
+        match command["type"].as_str().unwrap() {
+            "subscribe_entities" => {
+                *subscription.lock().unwrap() = command["id"].clone();
+                vec![
+                    result,
+                    json!({"id": command["id"], "type": "event", "event": {"a": {
+                        "light.kitchen": {
+                            "s": "on",
+                            "a": {"brightness": 255, "friendly_name": "Kitchen"},
+                            "c": "01HKZ",
+                            "lc": 1_704_110_400.5,
+                        },
+                        "sensor.temperature": {
+                            "s": "21.5",
+                            "a": {"unit_of_measurement": "°C"},
+                            "c": "01HL0",
+                            "lc": 1_704_110_000.0,
+                            "lu": 1_704_110_400.0,
+                        },
+                    }}}),
+                ]
+            }
+            // stand-in for HA noticing changes
+            "test/push" => vec![
+                json!({"id": *subscription.lock().unwrap(), "type": "event", "event": {
+                    "c": {"light.kitchen": {
+                        "+": {
+                            "s": "off",
+                            "c": {"id": "01HM0", "parent_id": null, "user_id": "abc"},
+                            "lc": 1_704_110_460.0,
+                        },
+                        "-": {"a": ["brightness"]},
+                    }},
+                    "a": {"light.porch": {"s": "on", "a": {}, "c": "01HM1", "lc": 1_704_110_460.0}},
+                    "r": ["sensor.temperature"],
+                }}),
+                result,
+            ],
+            "unsubscribe_events" => {
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push(command["subscription"].clone());
+                vec![result]
+            }
+            _ => vec![result],
+        }
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    let mirror = websocket.state_mirror(None).await.unwrap();
+    let kitchen = mirror.get("light.kitchen").unwrap();
+    assert_eq!(kitchen.state, "on");
+    assert_eq!(kitchen.attributes["brightness"], 255);
+    assert_eq!(
+        kitchen.last_changed.to_rfc3339(),
+        "2024-01-01T12:00:00.500+00:00"
+    );
+    assert_eq!(kitchen.context.unwrap().id, "01HKZ");
+    let temperature = mirror.get("sensor.temperature").unwrap();
+    assert_eq!(
+        temperature.last_updated.to_rfc3339(),
+        "2024-01-01T12:00:00+00:00"
+    );
+    assert!(temperature.last_reported.is_none());
+    assert_eq!(mirror.states().len(), 2);
+
+    let mut kitchen = mirror.watch("light.kitchen");
+    let mut porch = mirror.watch("light.porch");
+    assert!(porch.borrow().is_none());
+
+    websocket
+        .command::<serde_json::Value>(json!({"type": "test/push"}))
+        .await
+        .unwrap();
+
+    let kitchen = wait_for(&mut kitchen, |state| {
+        state.as_ref().map(|state| state.state == "off") == Some(true)
+    })
+    .await
+    .unwrap();
+    assert!(!kitchen.attributes.contains_key("brightness"));
+    assert_eq!(kitchen.attributes["friendly_name"], "Kitchen");
+    assert_eq!(kitchen.context.unwrap().user_id.as_deref(), Some("abc"));
+    assert_eq!(
+        kitchen.last_changed.to_rfc3339(),
+        "2024-01-01T12:01:00+00:00"
+    );
+    assert!(wait_for(&mut porch, Option::is_some).await.is_some());
+    assert!(mirror.get("sensor.temperature").is_none());
+
+    let mut entity_ids = mirror.entity_ids();
+    entity_ids.sort();
+    assert_eq!(entity_ids, vec!["light.kitchen", "light.porch"]);
+
+    // the subscription ends with the last clone even when HA sends nothing more
+    let id = subscription_id.lock().unwrap().clone();
+    drop(mirror);
+    for _ in 0..50 {
+        if !unsubscribed.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+    }
+    assert_eq!(*unsubscribed.lock().unwrap(), vec![id]);
+}
+
+#[tokio::test]
+async fn rendered_templates_are_streamed() {
+    let url = serve_websocket_messages(|command| {
+        if command["template"] == "{{ broken" {
+            assert!(command.get("strict").is_none());
+            assert!(command.get("report_errors").is_none());
+            return vec![json!({
+                "id": command["id"],
+                "type": "result",
+                "success": false,
+                "error": {"code": "template_error", "message": "TemplateSyntaxError: unexpected end of template"},
+            })];
+        }
+        assert_eq!(command["type"], "render_template");
+        assert_eq!(command["variables"], json!({"name": "kitchen"}));
+        assert_eq!(command["timeout"], 2.5);
+        assert_eq!(command["strict"], true);
+        assert_eq!(command["report_errors"], true);
+        vec![
+            json!({"id": command["id"], "type": "result", "success": true, "result": null}),
+            json!({"id": command["id"], "type": "event", "event": {
+                "result": 21.5,
+                "listeners": {"all": false, "domains": [], "entities": ["sensor.kitchen"], "time": false},
+            }}),
+            json!({"id": command["id"], "type": "event", "event": {
+                "error": "UndefinedError: 'None' has no attribute 'state'",
+                "level": "ERROR",
+            }}),
+        ]
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    let mut updates = websocket
+        .render_template(
+            "{{ states('sensor.' ~ name) | float }}",
+            Some(json!({"name": "kitchen"})),
+            Some(Duration::from_millis(2500)),
+            true,
+            true,
+        )
+        .await
+        .unwrap();
+    match updates.next().await.unwrap().unwrap() {
+        TemplateUpdate::Rendered(rendered) => {
+            assert_eq!(rendered.result, 21.5);
+            assert_eq!(rendered.listeners.entities, vec!["sensor.kitchen"]);
+        }
+        other => panic!("expected a render, got {:?}", other),
+    }
+    match updates.next().await.unwrap().unwrap() {
+        TemplateUpdate::Error(error) => assert_eq!(error.level, "ERROR"),
+        other => panic!("expected an error, got {:?}", other),
+    }
+
+    match websocket
+        .render_template("{{ broken", None, None, false, false)
+        .await
+    {
+        Err(Error::Command(code, _)) => assert_eq!(code, "template_error"),
+        other => panic!("expected a template error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn services_are_called_on_targets() {
+    let url = serve_websocket(|command| {
+        assert_eq!(command["type"], "call_service");
+        assert_eq!(command["domain"], "light");
+        assert_eq!(command["target"], json!({"area_id": ["kitchen"]}));
+        let context = json!({"id": "01HM2", "parent_id": null, "user_id": "abc"});
+        if command["return_response"] == true {
+            json!({"context": context, "response": {"todo.shopping": {"items": [{"summary": "Milk"}]}}})
+        } else {
+            assert_eq!(command["service_data"], json!({"brightness_pct": 50}));
+            assert!(command.get("return_response").is_none());
+            json!({"context": context, "response": null})
+        }
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+
+    let context = websocket
+        .call_service(
+            "light",
+            "turn_on",
+            Some(json!({"brightness_pct": 50})),
+            Some(ServiceTarget::areas(vec!["kitchen"])),
+        )
+        .await
+        .unwrap();
+    assert_eq!(context.user_id.as_deref(), Some("abc"));
+
+    let result = websocket
+        .call_service_with_response::<HashMap<String, serde_json::Value>>(
+            "light",
+            "get_items",
+            None,
+            Some(ServiceTarget::areas(vec!["kitchen"])),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        result.response["todo.shopping"]["items"][0]["summary"],
+        "Milk"
+    );
+}
+
+#[tokio::test]
+async fn long_term_statistics_are_typed() {
+    let url = serve_websocket(|command| match command["type"].as_str().unwrap() {
+        "recorder/statistics_during_period" => {
+            assert_eq!(command["start_time"], "2024-01-01T00:00:00Z");
+            assert_eq!(command["statistic_ids"], json!(["sensor.energy", "sensor.outside"]));
+            assert_eq!(command["period"], "5minute");
+            assert_eq!(command["units"], json!({"energy": "Wh"}));
+            assert_eq!(command["types"], json!(["change", "mean", "max"]));
+            assert!(command.get("end_time").is_none());
+            json!({
+                "sensor.energy": [
+                    {"start": 1_704_067_200_000.0, "end": 1_704_067_500_000.0, "change": 120.5},
+                ],
+                "sensor.outside": [
+                    {"start": 1_704_067_200_000.0, "end": 1_704_067_500_000.0, "mean": -2.1, "max": -1.5},
+                ],
+            })
+        }
+        "recorder/list_statistic_ids" => {
+            assert_eq!(command["statistic_type"], "sum");
+            json!([{
+                "statistic_id": "sensor.energy",
+                "name": "Energy",
+                "source": "recorder",
+                "has_mean": false,
+                "has_sum": true,
+                "statistics_unit_of_measurement": "kWh",
+                "display_unit_of_measurement": "kWh",
+                "unit_class": "energy",
+            }])
+        }
+        "recorder/get_statistics_metadata" => {
+            assert_eq!(command["statistic_ids"], json!(["sensor.outside"]));
+            json!([{
+                "statistic_id": "sensor.outside",
+                "name": null,
+                "source": "recorder",
+                "has_mean": true,
+                "has_sum": false,
+                "statistics_unit_of_measurement": "°C",
+                "unit_class": "temperature",
+            }])
+        }
+        other => panic!("unexpected command {}", other),
+    })
+    .await;
+    let websocket = api(url, "access")
+        .await
+        .get_websocket_client()
+        .await
+        .unwrap();
+    let start = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+
+    let statistics = websocket
+        .statistics_during_period(start)
+        .statistic_ids(vec!["sensor.energy", "sensor.outside"])
+        .period(StatisticsPeriod::FiveMinutes)
+        .unit("energy", "Wh")
+        .types(vec![
+            StatisticType::Change,
+            StatisticType::Mean,
+            StatisticType::Max,
+        ])
+        .send()
+        .await
+        .unwrap();
+    let energy = &statistics["sensor.energy"][0];
+    assert_eq!(energy.start, start);
+    assert_eq!(energy.end, start + chrono::Duration::minutes(5));
+    assert_eq!(energy.change, Some(120.5));
+    assert_eq!(energy.sum, None);
+    let outside = &statistics["sensor.outside"][0];
+    assert_eq!((outside.mean, outside.max), (Some(-2.1), Some(-1.5)));
+
+    let sums = websocket
+        .list_statistic_ids(Some(StatisticIdType::Sum))
+        .await
+        .unwrap();
+    assert!(sums[0].has_sum);
+    assert_eq!(sums[0].unit_class.as_deref(), Some("energy"));
+    let metadata = websocket
+        .statistics_metadata(&["sensor.outside"])
+        .await
+        .unwrap();
+    assert_eq!(
+        metadata[0].statistics_unit_of_measurement.as_deref(),
+        Some("°C")
+    );
+    assert!(metadata[0].display_unit_of_measurement.is_none());
+}
+
+#[test]
+fn statistic_id_types_serialize_as_ha_expects() {
+    assert_eq!(json!(StatisticIdType::Mean), json!("mean"));
+    assert_eq!(json!(StatisticIdType::Sum), json!("sum"));
+}